@@ -13,16 +13,17 @@ const hashString = (str) => {
 };
 
 const GAME_ID = 'roguelike_game';
-const WIDTH = 80;
-const HEIGHT = 50;
 const CANVAS_SCALE_X = 10;
 const CANVAS_SCALE_Y = 16;
 
 class GameRunner {
-  constructor(renderMode, container, rngSeed) {
+  constructor(renderMode, container, rngSeed, generator, config, level) {
     this.rngSeed = rngSeed;
+    this.generator = generator;
+    this.config = config;
+    this.level = level;
     this.container = container;
-    this.setRenderMode(renderMode);
+    this.renderMode = renderMode;
 
     // TODO: use babel-transform-class-properties
     this.render = this.render.bind(this);
@@ -55,8 +56,8 @@ class GameRunner {
           this.canvasCtx.backingStorePixelRatio ||
           1);
 
-      const w = WIDTH * CANVAS_SCALE_X;
-      const h = HEIGHT * CANVAS_SCALE_Y;
+      const w = this.width * CANVAS_SCALE_X;
+      const h = this.height * CANVAS_SCALE_Y;
 
       this.el.width = w * pixelRatio;
       this.el.height = h * pixelRatio;
@@ -78,7 +79,7 @@ class GameRunner {
       this.el.style.lineHeight = 1;
       this.el.style.fontFamily = "'Courier New', Courier, monospace";
       this.setupVDR();
-      this.renderBuffer = new Uint8Array(WIDTH * HEIGHT * 7);
+      this.renderBuffer = new Uint8Array(this.width * this.height * 7);
     } else throw new Error(`Unsupported render mode: ${renderMode}`);
 
     this.el.style.padding = 0;
@@ -87,9 +88,19 @@ class GameRunner {
   }
 
   async run() {
-    const { GameData } = await import('./pkg');
+    const { GameData, MapGenerator } = await import('./pkg');
+
+    this.game = new GameData(
+      this.rngSeed,
+      MapGenerator[this.generator],
+      this.config ? JSON.stringify(this.config) : undefined
+    );
+    if (this.level) this.game.load_level(this.level);
 
-    this.game = new GameData(this.rngSeed);
+    // the screen size comes from the game's config
+    this.width = this.game.width();
+    this.height = this.game.height();
+    this.setRenderMode(this.renderMode);
 
     this.iter = 0;
     window.requestAnimationFrame(this.render);
@@ -104,8 +115,8 @@ class GameRunner {
     while (this.el.hasChildNodes()) this.el.removeChild(this.el.lastChild);
 
     let x, y;
-    for (y = 0; y < HEIGHT; y++) {
-      for (x = 0; x < WIDTH; x++) {
+    for (y = 0; y < this.height; y++) {
+      for (x = 0; x < this.width; x++) {
         const $span = document.createElement('span');
         $span.style.backgroundColor = '#000000';
         $span.innerHTML = '&nbsp;';
@@ -136,8 +147,8 @@ class GameRunner {
           pixel,
           i = 0,
           j = 0;
-        for (y = 0; y < HEIGHT; y++) {
-          for (x = 0; x < WIDTH; x++) {
+        for (y = 0; y < this.height; y++) {
+          for (x = 0; x < this.width; x++) {
             pixel = this.vdr[i++];
 
             pixel.style.backgroundColor = `rgb(${r[j]},${r[j + 1]},${
@@ -178,7 +189,7 @@ class GameRunner {
     // TODO: do I need to clear WASM memory?
     // if (this.game) this.game.free();
 
-    this.el.remove();
+    if (this.el) this.el.remove();
   }
 }
 
@@ -186,11 +197,17 @@ class GameRunner {
  * @param {object} [options]
  * @param {'text'|'canvas_2d'} [options.renderMode='text']
  * @param {string} [options.seed] Game RNG seed, leave blank for random
+ * @param {'Rooms'|'Bsp'|'Cave'} [options.generator='Rooms'] dungeon generator
+ * @param {object} [options.config] game settings, see `GameConfig` in
+ *   src/config.rs. Settings that are left out keep their defaults
+ * @param {string} [options.level] a level file to play instead of the
+ *   generated first level, see src/level_file.rs
  * @param {string} [options.containerId] where to put game, default is document body
  * @return {{ dispose: Function }}
  */
 module.exports = (options = {}) => {
   const renderMode = options.renderMode || 'text';
+  const generator = options.generator || 'Rooms';
   const container = options.containerId
     ? document.getElementById(options.containerId)
     : document.body;
@@ -201,5 +218,12 @@ module.exports = (options = {}) => {
   if (options.containerId && !container)
     throw new Error(`Cannot find element with id containerId="${containerId}"`);
 
-  return new GameRunner(renderMode, container, rngSeed);
+  return new GameRunner(
+    renderMode,
+    container,
+    rngSeed,
+    generator,
+    options.config,
+    options.level
+  );
 };
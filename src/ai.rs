@@ -1,38 +1,446 @@
+use rand::Rng;
+use std::fmt;
+use std::mem;
+
 use crate::config::*;
-use crate::game::Game;
+use crate::effects::EffectKind;
+use crate::game::{Game, Goal};
+use crate::los;
 use crate::mem::mut_two;
 
-// pub trait Ai {
-//   fn action(&self, monster_id: usize, game: &mut Game);
-// }
+/// How a monster acts on its turn. Behaviours keep their own state, and can
+/// hand over to another behaviour, e.g. when a confusion wears off
+pub trait Behaviour: fmt::Debug {
+  /// take a turn. Returns the behaviour to switch to afterwards, if it changes
+  fn act(
+    &mut self,
+    monster_id: usize,
+    senses: &mut Senses,
+    game: &mut Game,
+  ) -> Option<Box<dyn Behaviour>>;
+
+  /// what the monster is up to, as shown next to its name
+  fn status(&self, senses: &Senses) -> Option<&'static str> {
+    senses.status()
+  }
+}
+
+/// how many turns a monster looks around where it lost track of the player
+const SEARCH_TURNS: i32 = 8;
+
+/// What a monster knows about the player
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Alertness {
+  Unaware,
+  /// going after the player, last seen at this position
+  Hunting((i32, i32)),
+  /// looking around where the player was last seen
+  Searching {
+    around: (i32, i32),
+    turns: i32,
+  },
+}
 
+/// What a monster can perceive, and remembers
 #[derive(Debug, Clone, Copy)]
+pub struct Senses {
+  /// how far the monster can see. A blind monster (0) only notices what's right next to it
+  pub vision: i32,
+  pub alertness: Alertness,
+}
+
+impl Senses {
+  pub fn status(&self) -> Option<&'static str> {
+    match self.alertness {
+      Alertness::Unaware => None,
+      Alertness::Hunting(_) => Some("hunting"),
+      Alertness::Searching { .. } => Some("searching"),
+    }
+  }
+
+  /// can the monster notice what's at (`x`, `y`), with its own eyes? It
+  /// sees the player exactly when the player's view takes it in, so nothing
+  /// can be attacked from where it can't see back
+  pub fn can_see(&self, monster_id: usize, game: &Game, (x, y): (i32, i32)) -> bool {
+    let (monster_x, monster_y) = game.objects[monster_id].pos();
+    let (dx, dy) = (x - monster_x, y - monster_y);
+    if dx.abs() <= 1 && dy.abs() <= 1 {
+      return true;
+    }
+    if dx * dx + dy * dy > self.vision * self.vision {
+      return false;
+    }
+
+    if (x, y) == game.objects[PLAYER].pos() {
+      game.fov.is_in_fov(monster_x, monster_y)
+    } else {
+      los::can_see(&game.map, (monster_x, monster_y), (x, y))
+    }
+  }
+}
+
+#[derive(Debug)]
 pub struct Ai {
-  pub speed: i32,
+  pub senses: Senses,
+  pub behaviour: Box<dyn Behaviour>,
 }
 
 impl Ai {
-  pub fn action(&self, monster_id: usize, game: &mut Game) {
-    if game.tick % (self.speed as u64) != 0 {
-      return;
+  pub fn new(vision: i32, behaviour: Box<dyn Behaviour>) -> Self {
+    Ai {
+      senses: Senses {
+        vision,
+        alertness: Alertness::Unaware,
+      },
+      behaviour,
+    }
+  }
+
+  pub fn action(&mut self, monster_id: usize, game: &mut Game) {
+    if let Some(next) = self.behaviour.act(monster_id, &mut self.senses, game) {
+      self.behaviour = next;
     }
+  }
 
-    // a basic monster takes its turn. If you can see it, it can see you
-    let (monster_x, monster_y) = game.objects[monster_id].pos();
-    if game.fov.is_in_fov(monster_x, monster_y)
-      && game.objects[PLAYER].fighter.map_or(false, |f| f.hp > 0)
-    {
-      let (dx, dy) = game.objects[monster_id].delta_to(&game.objects[PLAYER]);
-
-      // if (dx == 0 && dy.abs() <= 1) || (dy == 0 && dx.abs() <= 1) {
-      if dy.abs() <= 1 && dx.abs() <= 1 {
-        // close enough, attack!
-        let (monster, player) = mut_two(monster_id, PLAYER, &mut game.objects);
-        monster.attack(player, &mut game.messages);
+  /// what the monster is up to, e.g. "hunting"
+  pub fn status(&self) -> Option<&'static str> {
+    self.behaviour.status(&self.senses)
+  }
+
+  /// make the monster stumble around while it's confused, before going
+  /// back to what it was doing
+  pub fn confuse(&mut self) {
+    let previous = mem::replace(&mut self.behaviour, Box::new(Chaser::default()));
+    self.behaviour = Box::new(Confused {
+      previous: Some(previous),
+    });
+  }
+}
+
+fn player_alive(game: &Game) -> bool {
+  game.objects[PLAYER].fighter.map_or(false, |f| f.hp > 0)
+}
+
+/// go after the player: attack it when next to it, move towards it when it
+/// can be seen (all hunters share one map of the way there), or else go to where it was last seen and look around for a
+/// while. Returns false once the monster has no idea where the player is
+fn hunt(monster_id: usize, senses: &mut Senses, game: &mut Game) -> bool {
+  if !player_alive(game) {
+    senses.alertness = Alertness::Unaware;
+    return false;
+  }
+
+  let player_pos = game.objects[PLAYER].pos();
+  if senses.can_see(monster_id, game, player_pos) {
+    senses.alertness = Alertness::Hunting(player_pos);
+  }
+
+  match senses.alertness {
+    Alertness::Unaware => return false,
+    Alertness::Hunting(last_seen) if last_seen != player_pos => {
+      // the player got away: head to where it was last seen
+      if game.objects[monster_id].pos() == last_seen {
+        senses.alertness = Alertness::Searching {
+          around: last_seen,
+          turns: SEARCH_TURNS,
+        };
+      } else {
+        game.move_towards(monster_id, last_seen.0, last_seen.1);
+        return true;
+      }
+    }
+    _ => {}
+  }
+
+  if let Alertness::Searching { around, turns } = senses.alertness {
+    if turns <= 0 {
+      senses.alertness = Alertness::Unaware;
+      return false;
+    }
+    senses.alertness = Alertness::Searching {
+      around,
+      turns: turns - 1,
+    };
+
+    // poke around, without straying too far
+    let (x, y) = game.objects[monster_id].pos();
+    if (x - around.0).abs() > 2 || (y - around.1).abs() > 2 {
+      game.move_towards(monster_id, around.0, around.1);
+    } else {
+      stumble(monster_id, game);
+    }
+    return true;
+  }
+
+  let (dx, dy) = game.objects[monster_id].delta_to(&game.objects[PLAYER]);
+  if dy.abs() <= 1 && dx.abs() <= 1 {
+    // close enough, attack!
+    let (monster, player) = mut_two(monster_id, PLAYER, &mut game.objects);
+    monster.attack(player, &mut game.rng, &mut game.messages);
+  } else {
+    // move towards player if far away, finding a way around the pack if
+    // the shared map is blocked
+    if !game.move_to_goal(monster_id, Goal::Player) {
+      let (player_x, player_y) = game.objects[PLAYER].pos();
+      game.move_towards(monster_id, player_x, player_y);
+    }
+  }
+  true
+}
+
+/// take a step in a random direction
+fn stumble(monster_id: usize, game: &mut Game) {
+  let dx = game.rng.gen_range(-1, 2);
+  let dy = game.rng.gen_range(-1, 2);
+  game.move_by(monster_id, dx, dy);
+}
+
+/// A basic melee monster: if you can see it, it's coming for you. Otherwise
+/// it roams around looking for you
+#[derive(Debug, Default)]
+pub struct Chaser {
+  wanderer: Wanderer,
+}
+
+impl Behaviour for Chaser {
+  fn act(
+    &mut self,
+    monster_id: usize,
+    senses: &mut Senses,
+    game: &mut Game,
+  ) -> Option<Box<dyn Behaviour>> {
+    if hunt(monster_id, senses, game) {
+      self.wanderer.destination = None;
+    } else {
+      self.wanderer.wander(monster_id, game);
+    }
+    None
+  }
+}
+
+/// Roams from place to place until it spots the player
+#[derive(Debug, Default)]
+pub struct Wanderer {
+  destination: Option<(i32, i32)>,
+}
+
+impl Wanderer {
+  /// take a step towards a random spot of a random region, picking a new
+  /// one after getting there
+  fn wander(&mut self, monster_id: usize, game: &mut Game) {
+    if self.destination.is_none() || self.destination == Some(game.objects[monster_id].pos()) {
+      self.destination = if game.map.regions.is_empty() {
+        None
       } else {
-        // move towards player if far away
-        let (player_x, player_y) = game.objects[PLAYER].pos();
-        game.move_towards(monster_id, player_x, player_y);
+        let region = &game.map.regions[game.rng.gen_range(0, game.map.regions.len())];
+        Some(region.points[game.rng.gen_range(0, region.points.len())])
+      };
+    }
+
+    match self.destination {
+      Some((x, y)) => game.move_towards(monster_id, x, y),
+      None => stumble(monster_id, game),
+    }
+  }
+}
+
+impl Behaviour for Wanderer {
+  fn act(
+    &mut self,
+    monster_id: usize,
+    senses: &mut Senses,
+    game: &mut Game,
+  ) -> Option<Box<dyn Behaviour>> {
+    if hunt(monster_id, senses, game) {
+      self.destination = None;
+    } else {
+      self.wander(monster_id, game);
+    }
+    None
+  }
+
+  fn status(&self, senses: &Senses) -> Option<&'static str> {
+    senses.status().or(Some("wandering"))
+  }
+}
+
+/// Holds its post, only leaving it to fight the player nearby
+#[derive(Debug)]
+pub struct Guard {
+  pub post: (i32, i32),
+  /// how far from its post the guard is willing to go
+  pub range: i32,
+}
+
+impl Guard {
+  pub fn new(post: (i32, i32), range: i32) -> Self {
+    Guard { post, range }
+  }
+}
+
+impl Behaviour for Guard {
+  fn act(
+    &mut self,
+    monster_id: usize,
+    senses: &mut Senses,
+    game: &mut Game,
+  ) -> Option<Box<dyn Behaviour>> {
+    let (px, py) = game.objects[PLAYER].pos();
+    let (dx, dy) = (px - self.post.0, py - self.post.1);
+    if dx.abs() <= self.range && dy.abs() <= self.range {
+      if hunt(monster_id, senses, game) {
+        return None;
+      }
+    } else {
+      // too far away to bother
+      senses.alertness = Alertness::Unaware;
+    }
+
+    // back to the post
+    if game.objects[monster_id].pos() != self.post {
+      game.move_towards(monster_id, self.post.0, self.post.1);
+    }
+    None
+  }
+
+  fn status(&self, senses: &Senses) -> Option<&'static str> {
+    senses.status().or(Some("guarding"))
+  }
+}
+
+/// Fights like a chaser, but runs away when badly hurt
+#[derive(Debug, Default)]
+pub struct Coward {
+  fleeing: bool,
+  wanderer: Wanderer,
+}
+
+impl Behaviour for Coward {
+  fn act(
+    &mut self,
+    monster_id: usize,
+    senses: &mut Senses,
+    game: &mut Game,
+  ) -> Option<Box<dyn Behaviour>> {
+    let wounded = match game.objects[monster_id].fighter {
+      Some(f) => f.hp * 4 <= f.max_hp,
+      None => false,
+    };
+    let sees_player = senses.can_see(monster_id, game, game.objects[PLAYER].pos());
+    self.fleeing = wounded && sees_player && game.move_to_goal(monster_id, Goal::AwayFromPlayer);
+    if self.fleeing || hunt(monster_id, senses, game) {
+      self.wanderer.destination = None;
+    } else {
+      self.wanderer.wander(monster_id, game);
+    }
+    None
+  }
+
+  fn status(&self, senses: &Senses) -> Option<&'static str> {
+    if self.fleeing {
+      Some("fleeing")
+    } else {
+      senses.status()
+    }
+  }
+}
+
+/// Stumbles around at random until the confusion wears off, then goes back
+/// to what it was doing
+#[derive(Debug)]
+pub struct Confused {
+  previous: Option<Box<dyn Behaviour>>,
+}
+
+impl Behaviour for Confused {
+  fn act(
+    &mut self,
+    monster_id: usize,
+    senses: &mut Senses,
+    game: &mut Game,
+  ) -> Option<Box<dyn Behaviour>> {
+    if !game.objects[monster_id].has_effect(EffectKind::Confusion) {
+      let mut previous = self
+        .previous
+        .take()
+        .unwrap_or_else(|| Box::new(Chaser::default()));
+      let next = previous.act(monster_id, senses, game);
+      return Some(next.unwrap_or(previous));
+    }
+
+    stumble(monster_id, game);
+    None
+  }
+
+  fn status(&self, _senses: &Senses) -> Option<&'static str> {
+    Some("confused")
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::colors;
+  use crate::fov::{FovKind, FOV};
+  use crate::map::MapGenerator;
+  use crate::object::Object;
+
+  /// let a blind monster act for a while, far away from the player, and
+  /// return where it went
+  fn roam(behaviour: Box<dyn Behaviour>) -> Vec<(i32, i32)> {
+    let mut game = Game::new(4, MapGenerator::Rooms, GameConfig::default());
+    game.objects.truncate(PLAYER + 1);
+    let (x, y) = game.map.regions.last().unwrap().center;
+    let mut monster = Object::new(x, y, 'o', colors::WHITE, "orc", true);
+    monster.alive = true;
+    game.objects.push(monster);
+
+    let mut ai = Ai::new(0, behaviour);
+    (0..30)
+      .map(|_| {
+        ai.action(PLAYER + 1, &mut game);
+        assert_eq!(ai.senses.alertness, Alertness::Unaware);
+        game.objects[PLAYER + 1].pos()
+      })
+      .collect()
+  }
+
+  #[test]
+  fn unaware_monsters_wander() {
+    let moves = |positions: Vec<(i32, i32)>| positions.windows(2).any(|step| step[0] != step[1]);
+    assert!(moves(roam(Box::new(Chaser::default()))));
+    assert!(moves(roam(Box::new(Coward::default()))));
+    assert!(moves(roam(Box::new(Wanderer::default()))));
+  }
+
+  #[test]
+  fn monsters_see_the_player_when_the_player_sees_them() {
+    for &generator in &[MapGenerator::Rooms, MapGenerator::Cave] {
+      let mut game = Game::new(3, generator, GameConfig::default());
+      let (player_x, player_y) = game.objects[PLAYER].pos();
+      let (radius, light_walls) = (game.config.sight_radius, game.config.fov_light_walls);
+      game
+        .fov
+        .compute_fov(player_x, player_y, radius, light_walls);
+
+      let mut sight = FOV::from_map(&game.map, FovKind::SymmetricShadowcasting);
+      let senses = Ai::new(8, Box::new(Chaser::default())).senses;
+      game.objects.truncate(PLAYER + 1);
+      game
+        .objects
+        .push(Object::new(0, 0, 'o', colors::WHITE, "orc", true));
+      for x in 0..game.map.width {
+        for y in 0..game.map.height {
+          let (dx, dy) = (x - player_x, y - player_y);
+          if game.map.tile_at(x, y).block_sight() || dx * dx + dy * dy > 64 {
+            continue;
+          }
+
+          game.objects[PLAYER + 1].set_pos(x, y);
+          sight.compute_fov(x, y, radius, light_walls);
+          let sees = senses.can_see(PLAYER + 1, &game, (player_x, player_y));
+          assert_eq!(sees, sight.is_in_fov(player_x, player_y), "{:?}", (x, y));
+        }
       }
     }
   }
@@ -0,0 +1,120 @@
+use rand::Rng;
+
+use crate::config::GameConfig;
+use crate::map::Map;
+use crate::rect::Rect;
+
+/*
+ * Binary space partition dungeon generator: the map area is recursively split
+ * into two partitions ("leaves") until they are small enough, then a room is
+ * placed inside every leaf and sibling leaves are linked with a tunnel.
+ */
+
+struct Leaf {
+  area: Rect,
+  children: Option<(Box<Leaf>, Box<Leaf>)>,
+}
+
+impl Leaf {
+  fn new(area: Rect) -> Self {
+    Leaf {
+      area,
+      children: None,
+    }
+  }
+
+  fn width(&self) -> i32 {
+    self.area.x2 - self.area.x1
+  }
+
+  fn height(&self) -> i32 {
+    self.area.y2 - self.area.y1
+  }
+
+  /// split this leaf in two (and its children, recursively)
+  fn split<R: Rng>(&mut self, rng: &mut R, config: &GameConfig) {
+    let (w, h) = (self.width(), self.height());
+
+    // small enough leaves are only split some of the time, for variety
+    if w <= config.bsp_max_leaf_size && h <= config.bsp_max_leaf_size && rng.gen::<f32>() < 0.25 {
+      return;
+    }
+
+    // split across the longest side, or randomly if the leaf is roughly square
+    let horizontal = if w as f32 / h as f32 >= 1.25 {
+      false
+    } else if h as f32 / w as f32 >= 1.25 {
+      true
+    } else {
+      rng.gen::<bool>()
+    };
+
+    let size = if horizontal { h } else { w };
+    if size < config.bsp_min_leaf_size * 2 {
+      // too small to split any further
+      return;
+    }
+
+    let at = rng.gen_range(
+      config.bsp_min_leaf_size,
+      size - config.bsp_min_leaf_size + 1,
+    );
+    let Rect { x1, y1, .. } = self.area;
+    let (first, second) = if horizontal {
+      (Rect::new(x1, y1, w, at), Rect::new(x1, y1 + at, w, h - at))
+    } else {
+      (Rect::new(x1, y1, at, h), Rect::new(x1 + at, y1, w - at, h))
+    };
+
+    let mut first = Leaf::new(first);
+    let mut second = Leaf::new(second);
+    first.split(rng, config);
+    second.split(rng, config);
+    self.children = Some((Box::new(first), Box::new(second)));
+  }
+
+  /// carve the rooms of this leaf into the map, returning the index of the
+  /// room (in `map.rooms`) that should be used to connect to its sibling
+  fn create_rooms<R: Rng>(&self, map: &mut Map, rng: &mut R, config: &GameConfig) -> usize {
+    match self.children {
+      Some((ref first, ref second)) => {
+        let first_room = first.create_rooms(map, rng, config);
+        let second_room = second.create_rooms(map, rng, config);
+
+        // link the two halves together
+        let (from, to) = (map.rooms[first_room], map.rooms[second_room]);
+        map.connect_rooms(&from, &to, rng);
+
+        // pick either side to represent this leaf when connecting upwards
+        if rng.gen::<bool>() {
+          first_room
+        } else {
+          second_room
+        }
+      }
+      None => {
+        // random room size that fits inside the leaf
+        let max_size = config.room_max_size;
+        let w = rng.gen_range(config.room_min_size, max_size.min(self.width()) + 1);
+        let h = rng.gen_range(config.room_min_size, max_size.min(self.height()) + 1);
+        // random position inside the leaf
+        let x = rng.gen_range(self.area.x1, self.area.x2 - w + 1);
+        let y = rng.gen_range(self.area.y1, self.area.y2 - h + 1);
+
+        let room = Rect::new(x, y, w, h);
+        map.create_room(room);
+        map.rooms.push(room);
+
+        map.rooms.len() - 1
+      }
+    }
+  }
+}
+
+/// fill the map with rooms using binary space partitioning
+pub fn generate<R: Rng>(map: &mut Map, rng: &mut R, config: &GameConfig) {
+  // keep the outer edge of the map as solid wall
+  let mut root = Leaf::new(Rect::new(0, 0, map.width - 1, map.height - 1));
+  root.split(rng, config);
+  root.create_rooms(map, rng, config);
+}
@@ -0,0 +1,148 @@
+use rand::Rng;
+use std::cmp;
+
+use crate::config::GameConfig;
+use crate::connectivity;
+use crate::map::{Map, Region, Tile};
+use crate::terrain::Terrain;
+
+/*
+ * Cellular automata cave generator: the map is randomly filled with walls,
+ * then smoothed a few times so that walls clump together into cave walls.
+ * Pockets that are too small are filled in and the rest get connected.
+ */
+
+/// fill the map with caves, and split them into spawn regions
+pub fn generate<R: Rng>(map: &mut Map, rng: &mut R, config: &GameConfig) {
+  // `true` means wall. Start with random noise, keeping a solid border
+  let mut walls = vec![vec![true; map.height as usize]; map.width as usize];
+  for x in 1..(map.width - 1) {
+    for y in 1..(map.height - 1) {
+      walls[x as usize][y as usize] = rng.gen::<f32>() < config.cave_fill_chance;
+    }
+  }
+
+  for _ in 0..config.cave_smoothing_passes {
+    walls = smooth(&walls);
+  }
+
+  for x in 0..map.width {
+    for y in 0..map.height {
+      if !walls[x as usize][y as usize] {
+        map.set_tile(x, y, Tile::empty());
+      }
+    }
+  }
+
+  // find all the separate pockets, largest first
+  let mut pockets = connectivity::find_areas(map);
+  pockets.sort_by_key(|pocket| cmp::Reverse(pocket.len()));
+
+  // the noise may have filled the whole map, so dig out somewhere to stand:
+  // the sector in the middle, which makes for a spawn region
+  if pockets.is_empty() {
+    let size = config.cave_region_size;
+    let (sx, sy) = (map.width / 2 / size * size, map.height / 2 / size * size);
+    let mut pocket = vec![];
+    for x in sx.max(1)..(sx + size).min(map.width - 1) {
+      for y in sy.max(1)..(sy + size).min(map.height - 1) {
+        map.set_tile(x, y, Tile::empty());
+        pocket.push((x, y));
+      }
+    }
+    pockets.push(pocket);
+  }
+
+  // fill in pockets that are too small to be worth visiting
+  // (always keep the largest one, no matter its size)
+  while pockets.len() > 1 && pockets[pockets.len() - 1].len() < config.cave_min_pocket_size {
+    for (x, y) in pockets.pop().unwrap() {
+      map.set_tile(x, y, Tile::wall());
+    }
+  }
+
+  // join every other pocket to the largest one, through their closest points
+  for i in 1..pockets.len() {
+    let (from, to) = connectivity::closest_points(&pockets[i], &pockets[0]);
+    map.connect_points(from, to, rng);
+  }
+
+  decorate(map, &pockets[0], rng, config);
+
+  map.regions = split_into_regions(map, config);
+}
+
+/// add pools of water, patches of grass and the odd lava pit or chasm to the cave
+fn decorate<R: Rng>(map: &mut Map, open: &[(i32, i32)], rng: &mut R, config: &GameConfig) {
+  let random_spot = |rng: &mut R| open[rng.gen_range(0, open.len())];
+
+  for _ in 0..config.cave_pools {
+    let (spot, radius) = (random_spot(rng), rng.gen_range(2, 5));
+    map.paint_blob(rng, spot, radius, Terrain::DeepWater, Terrain::ShallowWater);
+  }
+  for _ in 0..config.cave_grass_patches {
+    let (spot, radius) = (random_spot(rng), rng.gen_range(2, 6));
+    map.paint_blob(rng, spot, radius, Terrain::Grass, Terrain::Grass);
+  }
+  if rng.gen::<f32>() < config.cave_hazard_chance {
+    let (spot, radius) = (random_spot(rng), rng.gen_range(2, 4));
+    map.paint_blob(rng, spot, radius, Terrain::Lava, Terrain::Rubble);
+  }
+  if rng.gen::<f32>() < config.cave_hazard_chance {
+    let (spot, radius) = (random_spot(rng), rng.gen_range(2, 4));
+    map.paint_blob(rng, spot, radius, Terrain::Chasm, Terrain::Rubble);
+  }
+}
+
+/// run one step of the cellular automaton
+fn smooth(walls: &[Vec<bool>]) -> Vec<Vec<bool>> {
+  let mut next = walls.to_vec();
+  let (width, height) = (walls.len() as i32, walls[0].len() as i32);
+
+  for x in 1..(width - 1) {
+    for y in 1..(height - 1) {
+      let mut neighbors = 0;
+      for dx in -1..=1 {
+        for dy in -1..=1 {
+          if (dx != 0 || dy != 0) && walls[(x + dx) as usize][(y + dy) as usize] {
+            neighbors += 1;
+          }
+        }
+      }
+
+      // surrounded by walls: become a wall. Mostly open: become floor
+      if neighbors > 4 {
+        next[x as usize][y as usize] = true;
+      } else if neighbors < 4 {
+        next[x as usize][y as usize] = false;
+      }
+    }
+  }
+
+  next
+}
+
+/// chop the open tiles of the map into square sectors that can be used like rooms
+fn split_into_regions(map: &Map, config: &GameConfig) -> Vec<Region> {
+  let mut regions = vec![];
+  let size = config.cave_region_size;
+
+  for sector_x in (0..map.width).step_by(size as usize) {
+    for sector_y in (0..map.height).step_by(size as usize) {
+      let mut points = vec![];
+      for x in sector_x..(sector_x + size).min(map.width) {
+        for y in sector_y..(sector_y + size).min(map.height) {
+          if !map.tile_at(x, y).blocked() {
+            points.push((x, y));
+          }
+        }
+      }
+
+      if points.len() >= config.cave_min_region_size {
+        regions.push(Region::from_points(points));
+      }
+    }
+  }
+
+  regions
+}
@@ -1,5 +1,6 @@
-#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
-// #[cfg_attr(feature = "serialization", derive(Serialize, Deserialize))]
+use serde::Deserialize;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize)]
 pub struct Color {
   pub r: u8,
   pub g: u8,
@@ -1,50 +1,327 @@
-use crate::colors::Color;
+use serde::Deserialize;
 
-// actual size of the window
-pub const SCREEN_WIDTH: i32 = 80;
-pub const SCREEN_HEIGHT: i32 = 50;
-
-// size of the map
-pub const MAP_WIDTH: i32 = 80;
-pub const MAP_HEIGHT: i32 = 43;
-
-pub const LIMIT_FPS: i32 = 20; // 20 frames-per-second maximum
-
-// parameters for dungeon generator
-pub const ROOM_MAX_SIZE: i32 = 10;
-pub const ROOM_MIN_SIZE: i32 = 6;
-pub const MAX_ROOMS: i32 = 30;
-
-// Colors
-pub const COLOR_DARK_WALL: Color = Color { r: 0, g: 0, b: 100 };
-pub const COLOR_LIGHT_WALL: Color = Color {
-  r: 130,
-  g: 110,
-  b: 50,
-};
-pub const COLOR_DARK_GROUND: Color = Color {
-  r: 50,
-  g: 50,
-  b: 150,
-};
-pub const COLOR_LIGHT_GROUND: Color = Color {
-  r: 200,
-  g: 180,
-  b: 50,
-};
-
-pub const FOV_LIGHT_WALLS: bool = true; // light walls or not
-pub const TORCH_RADIUS: i32 = 10;
-
-pub const MAX_ROOM_MONSTERS: i32 = 2;
+use crate::colors::Color;
+use crate::dice::Dice;
+use crate::fov::FovKind;
 
 pub const PLAYER: usize = 0;
 
-// sizes and coordinates relevant for the GUI
-pub const BAR_WIDTH: i32 = 20;
-pub const PANEL_HEIGHT: i32 = 7;
-pub const PANEL_Y: i32 = SCREEN_HEIGHT - PANEL_HEIGHT;
+/// with more rock than this, the cave automaton fills everything in
+const MAX_CAVE_FILL_CHANCE: f32 = 0.6;
+/// rows needed to show the player's stats in the panel
+const MIN_PANEL_HEIGHT: i32 = 7;
+
+/// A value that changes with the dungeon level: `value` applies from `level` onwards
+#[derive(Clone, Debug, Deserialize)]
+pub struct Transition {
+  pub level: i32,
+  pub value: i32,
+}
+
+/// a table of transitions needs at least one, with the levels in order
+fn is_valid_table(table: &[Transition]) -> bool {
+  !table.is_empty()
+    && table.iter().all(|transition| transition.value >= 0)
+    && table.windows(2).all(|pair| pair[0].level < pair[1].level)
+}
+
+/// Colors of the map tiles that have no color of their own
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct Palette {
+  pub dark_wall: Color,
+  pub light_wall: Color,
+  pub dark_ground: Color,
+  pub light_ground: Color,
+}
+
+impl Default for Palette {
+  fn default() -> Self {
+    Palette {
+      dark_wall: Color::new(0, 0, 100),
+      light_wall: Color::new(130, 110, 50),
+      dark_ground: Color::new(50, 50, 150),
+      light_ground: Color::new(200, 180, 50),
+    }
+  }
+}
+
+/// Settings of a game that can be changed without rebuilding it, e.g. from
+/// JavaScript or a JSON file. Missing fields keep their default value
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct GameConfig {
+  // the world only moves when the player does, rather than in real time
+  pub turn_based: bool,
+
+  // actual size of the window
+  pub screen_width: i32,
+  pub screen_height: i32,
+
+  // size of the map
+  pub map_width: i32,
+  pub map_height: i32,
+
+  // parameters for dungeon generator
+  pub room_max_size: i32,
+  pub room_min_size: i32,
+  pub max_rooms: i32,
+
+  // parameters for the BSP dungeon generator
+  pub bsp_min_leaf_size: i32,
+  pub bsp_max_leaf_size: i32,
+
+  // parameters for the cave generator
+  pub cave_fill_chance: f32,
+  pub cave_smoothing_passes: i32,
+  pub cave_min_pocket_size: usize,
+  pub cave_region_size: i32,
+  pub cave_min_region_size: usize,
+  pub cave_pools: i32,
+  pub cave_grass_patches: i32,
+  pub cave_hazard_chance: f32, // chance of a lava pit (or chasm) per level
+
+  // chance for a tunnel entering a room to get a door
+  pub door_chance: f32,
+
+  // chance for a room to be littered with rubble
+  pub rubble_chance: f32,
+
+  // parameters for stamping vaults into maps
+  pub max_vaults: i32,
+  pub vault_placement_tries: i32,
+
+  pub fov_algorithm: FovKind,
+  pub fov_light_walls: bool, // light walls or not
+  pub sight_radius: i32,     // how far the player can see, if there's light
+
+  // the player's torch, and the light everywhere else
+  pub torch_radius: i32,
+  pub torch_color: Color,
+  pub ambient_light: Color,
+
+  // chance for a room to get a brazier
+  pub brazier_chance: f32,
+
+  // parameters for monster path-finding
+  pub path_diagonals: bool,
+  pub path_max_nodes: usize, // most tiles to search, per monster and turn
+  pub path_crowd_cost: i32,  // extra cost of walking through other monsters
+
+  // chance for a monster to roam the level rather than wait in its room
+  pub wanderer_chance: f32,
+
+  // maximum number of monsters per room
+  pub max_room_monsters: Vec<Transition>,
+  // chance (out of 100) that a monster is a troll rather than an orc
+  pub troll_chance: Vec<Transition>,
+
+  // how hard everyone hits (monsters on the first level), like "1d6+2"
+  pub player_damage: Dice,
+  pub troll_damage: Dice,
+  pub orc_damage: Dice,
+
+  // sizes and coordinates relevant for the GUI
+  pub bar_width: i32,
+  pub panel_height: i32,
+
+  pub palette: Palette,
+}
+
+impl Default for GameConfig {
+  fn default() -> Self {
+    GameConfig {
+      turn_based: true,
+
+      screen_width: 80,
+      screen_height: 50,
+
+      map_width: 80,
+      map_height: 43,
+
+      room_max_size: 10,
+      room_min_size: 6,
+      max_rooms: 30,
+
+      bsp_min_leaf_size: 8,
+      bsp_max_leaf_size: 20,
+
+      cave_fill_chance: 0.45,
+      cave_smoothing_passes: 5,
+      cave_min_pocket_size: 20,
+      cave_region_size: 10,
+      cave_min_region_size: 12,
+      cave_pools: 3,
+      cave_grass_patches: 4,
+      cave_hazard_chance: 0.3,
+
+      door_chance: 0.6,
+
+      rubble_chance: 0.3,
+
+      max_vaults: 2,
+      vault_placement_tries: 50,
+
+      fov_algorithm: FovKind::SymmetricShadowcasting,
+      fov_light_walls: true,
+      sight_radius: 20,
+
+      torch_radius: 10,
+      torch_color: Color::new(255, 230, 190),
+      ambient_light: Color::new(0, 0, 0),
+
+      brazier_chance: 0.3,
+
+      path_diagonals: true,
+      path_max_nodes: 200,
+      path_crowd_cost: 5,
+
+      wanderer_chance: 0.25,
+
+      max_room_monsters: vec![
+        Transition { level: 1, value: 2 },
+        Transition { level: 4, value: 3 },
+        Transition { level: 6, value: 5 },
+      ],
+      troll_chance: vec![
+        Transition {
+          level: 1,
+          value: 20,
+        },
+        Transition {
+          level: 3,
+          value: 30,
+        },
+        Transition {
+          level: 5,
+          value: 45,
+        },
+        Transition {
+          level: 7,
+          value: 60,
+        },
+      ],
+
+      player_damage: Dice::new(1, 6, 2),
+      troll_damage: Dice::new(1, 6, 1),
+      orc_damage: Dice::new(1, 4, 1),
+
+      bar_width: 20,
+      panel_height: 7,
+
+      palette: Palette::default(),
+    }
+  }
+}
+
+impl GameConfig {
+  /// read a config from JSON, e.g. `{ "map_width": 60, "torch_radius": 6 }`
+  pub fn from_json(json: &str) -> Result<Self, String> {
+    let config: GameConfig = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    config.validate()?;
+    Ok(config)
+  }
+
+  /// check that the settings make sense together
+  pub fn validate(&self) -> Result<(), String> {
+    if self.map_width > self.screen_width || self.map_height > self.panel_y() {
+      return Err("the map doesn't fit on the screen above the panel".into());
+    }
+    if self.room_min_size < 3 || self.room_min_size > self.room_max_size {
+      return Err("rooms need a minimum size of at least 3, and no more than their maximum".into());
+    }
+    if self.room_max_size + 2 > self.map_width || self.room_max_size + 2 > self.map_height {
+      return Err("rooms don't fit inside the map".into());
+    }
+    if self.max_rooms < 1 {
+      return Err("there has to be room for at least one room".into());
+    }
+    if self.bsp_min_leaf_size < self.room_min_size {
+      return Err("BSP leaves must fit the smallest room".into());
+    }
+    if self.cave_region_size <= 0 {
+      return Err("cave regions must have a positive size".into());
+    }
+    if self.cave_min_region_size > (self.cave_region_size * self.cave_region_size) as usize {
+      return Err("cave regions can't need more open tiles than fit in them".into());
+    }
+    if self.cave_fill_chance < 0.0 || self.cave_fill_chance > MAX_CAVE_FILL_CHANCE {
+      return Err(format!(
+        "the cave fill chance must be between 0 and {}, or caves fill up with rock",
+        MAX_CAVE_FILL_CHANCE
+      ));
+    }
+    if self.panel_height < MIN_PANEL_HEIGHT || self.panel_height >= self.screen_height {
+      return Err(format!(
+        "the panel needs at least {} rows, and has to fit on the screen",
+        MIN_PANEL_HEIGHT
+      ));
+    }
+    if self.bar_width < 1 || self.msg_width() < 1 {
+      return Err("the HP bar and the messages both need room in the panel".into());
+    }
+    if self.torch_radius < 1 || self.sight_radius < 1 {
+      return Err("the torch and sight radius must be at least 1".into());
+    }
+    for (name, dice) in &[
+      ("player_damage", self.player_damage),
+      ("troll_damage", self.troll_damage),
+      ("orc_damage", self.orc_damage),
+    ] {
+      if dice.max() < 1 {
+        return Err(format!("`{}` of {} can never do any damage", name, dice));
+      }
+    }
+    for (name, table) in &[
+      ("max_room_monsters", &self.max_room_monsters),
+      ("troll_chance", &self.troll_chance),
+    ] {
+      if !is_valid_table(table) {
+        return Err(format!(
+          "`{}` needs at least one value, with no negative values and levels going up",
+          name
+        ));
+      }
+    }
+    Ok(())
+  }
+
+  pub fn panel_y(&self) -> i32 {
+    self.screen_height - self.panel_height
+  }
+
+  pub fn msg_x(&self) -> i32 {
+    self.bar_width + 2
+  }
+
+  pub fn msg_width(&self) -> i32 {
+    self.screen_width - self.bar_width - 3
+  }
+
+  pub fn msg_height(&self) -> usize {
+    self.panel_height as usize - 1
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn default_is_valid() {
+    assert_eq!(GameConfig::default().validate(), Ok(()));
+  }
 
-pub const MSG_X: i32 = BAR_WIDTH + 2;
-pub const MSG_WIDTH: i32 = SCREEN_WIDTH - BAR_WIDTH - 3;
-pub const MSG_HEIGHT: usize = PANEL_HEIGHT as usize - 1;
+  #[test]
+  fn rejects_settings_that_leave_nowhere_to_start() {
+    for json in &[
+      r#"{ "max_rooms": 0 }"#,
+      r#"{ "cave_region_size": 10, "cave_min_region_size": 101 }"#,
+    ] {
+      assert!(GameConfig::from_json(json).is_err(), "{}", json);
+    }
+    assert!(
+      GameConfig::from_json(r#"{ "cave_region_size": 10, "cave_min_region_size": 100 }"#).is_ok()
+    );
+  }
+}
@@ -0,0 +1,208 @@
+use rand::Rng;
+use std::mem;
+
+use crate::map::{Map, Region, Tile};
+
+/// A group of open tiles that are connected to each other
+pub type Area = Vec<(i32, i32)>;
+
+/// Problems found in a map by `validate`
+#[derive(Debug, Default)]
+pub struct Report {
+  /// groups of open tiles that can't be reached from where the player starts
+  pub unreachable: Vec<Area>,
+  /// spawn points that are inside walls, so nothing can be placed there
+  pub blocked_spawns: Vec<(i32, i32)>,
+}
+
+impl Report {
+  pub fn is_ok(&self) -> bool {
+    self.unreachable.is_empty() && self.blocked_spawns.is_empty()
+  }
+}
+
+/// flood fill the open tiles of the map into separate connected areas.
+/// Only orthogonal steps count, since that is how the player moves. Doors
+/// (even locked ones) don't separate areas
+pub fn find_areas(map: &Map) -> Vec<Area> {
+  let mut visited = vec![vec![false; map.height as usize]; map.width as usize];
+  let mut areas = vec![];
+
+  for x in 0..map.width {
+    for y in 0..map.height {
+      if !map.tile_at(x, y).terrain.is_passable() || visited[x as usize][y as usize] {
+        continue;
+      }
+
+      let mut area = vec![];
+      let mut stack = vec![(x, y)];
+      visited[x as usize][y as usize] = true;
+
+      while let Some((cx, cy)) = stack.pop() {
+        area.push((cx, cy));
+        for &(nx, ny) in &[(cx + 1, cy), (cx - 1, cy), (cx, cy + 1), (cx, cy - 1)] {
+          if nx >= 0
+            && ny >= 0
+            && nx < map.width
+            && ny < map.height
+            && map.tile_at(nx, ny).terrain.is_passable()
+            && !visited[nx as usize][ny as usize]
+          {
+            visited[nx as usize][ny as usize] = true;
+            stack.push((nx, ny));
+          }
+        }
+      }
+
+      areas.push(area);
+    }
+  }
+
+  areas
+}
+
+/// the pair of points (one from each list) that are closest to each other
+pub fn closest_points(a: &[(i32, i32)], b: &[(i32, i32)]) -> ((i32, i32), (i32, i32)) {
+  a.iter()
+    .flat_map(|&from| b.iter().map(move |&to| (from, to)))
+    .min_by_key(|((ax, ay), (bx, by))| (ax - bx).pow(2) + (ay - by).pow(2))
+    .unwrap()
+}
+
+/// split the open areas of the map into the one the player starts in, and the rest
+fn split_main_area(map: &Map) -> (Area, Vec<Area>) {
+  let mut areas = find_areas(map);
+  if areas.is_empty() {
+    return (vec![], vec![]);
+  }
+
+  // the player starts at the center of the first region. Without regions,
+  // the largest area is as good as any
+  let main = map
+    .regions
+    .first()
+    .and_then(|region| areas.iter().position(|area| area.contains(&region.center)))
+    .unwrap_or_else(|| (0..areas.len()).max_by_key(|&i| areas[i].len()).unwrap());
+
+  let main_area = areas.remove(main);
+  (main_area, areas)
+}
+
+/// check that every open tile can be reached and that every spawn point is open
+pub fn validate(map: &Map) -> Report {
+  let (_, unreachable) = split_main_area(map);
+
+  let mut blocked_spawns = vec![];
+  for region in &map.regions {
+    for &(x, y) in region.points.iter().chain(Some(&region.center)) {
+      if map.tile_at(x, y).blocked() && !blocked_spawns.contains(&(x, y)) {
+        blocked_spawns.push((x, y));
+      }
+    }
+  }
+  for &(x, y, _) in &map.spawns {
+    if map.tile_at(x, y).blocked() && !blocked_spawns.contains(&(x, y)) {
+      blocked_spawns.push((x, y));
+    }
+  }
+
+  Report {
+    unreachable,
+    blocked_spawns,
+  }
+}
+
+/// fix the problems that `validate` finds: dig tunnels to the unreachable areas
+/// and make sure that every spawn point is open
+pub fn repair<R: Rng>(map: &mut Map, rng: &mut R) {
+  // objects placed by hand need their exact spot, so dig it out
+  for i in 0..map.spawns.len() {
+    let (x, y, _) = map.spawns[i];
+    if map.tile_at(x, y).blocked() {
+      map.set_tile(x, y, Tile::empty());
+    }
+  }
+
+  // regions just forget about their blocked points
+  let mut regions = mem::take(&mut map.regions);
+  for region in regions.iter_mut() {
+    region.points.retain(|&(x, y)| !map.tile_at(x, y).blocked());
+    let (cx, cy) = region.center;
+    if map.tile_at(cx, cy).blocked() && !region.points.is_empty() {
+      *region = Region::from_points(mem::take(&mut region.points));
+    }
+  }
+  regions.retain(|region| !region.points.is_empty());
+  map.regions = regions;
+
+  // connect every unreachable area to the main one, through their closest points
+  let (main_area, unreachable) = split_main_area(map);
+  for area in unreachable {
+    let (from, to) = closest_points(&area, &main_area);
+    map.connect_points(from, to, rng);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use pcg_rand::{seeds::PcgSeeder, Pcg32Basic};
+  use rand::SeedableRng;
+
+  use super::{repair, validate};
+  use crate::config::GameConfig;
+  use crate::level_file;
+  use crate::map::{Map, MapGenerator};
+
+  /// generated maps are repaired as they're made, so nothing should be left to report
+  fn assert_valid(generator: MapGenerator) {
+    for seed in 0..20 {
+      let mut rng = Pcg32Basic::from_seed(PcgSeeder::seed(seed));
+      let map = Map::new(&mut rng, generator, &GameConfig::default());
+      let report = validate(&map);
+      assert!(
+        report.is_ok(),
+        "{:?} map {} has problems: {:?}",
+        generator,
+        seed,
+        report
+      );
+    }
+  }
+
+  #[test]
+  fn rooms_are_connected() {
+    assert_valid(MapGenerator::Rooms);
+  }
+
+  #[test]
+  fn bsp_is_connected() {
+    assert_valid(MapGenerator::Bsp);
+  }
+
+  #[test]
+  fn caves_are_connected() {
+    assert_valid(MapGenerator::Cave);
+  }
+
+  #[test]
+  fn repair_connects_separate_rooms() {
+    let text = "\
+== map
+##########
+#@..#....#
+#...#.o..#
+##########
+== rooms
+0 0 4 3
+4 0 9 3
+";
+    let mut map = level_file::parse(text, 80, 50).unwrap();
+    let report = validate(&map);
+    assert_eq!(report.unreachable.len(), 1);
+    assert!(report.blocked_spawns.is_empty());
+
+    let mut rng = Pcg32Basic::from_seed(PcgSeeder::seed(0));
+    repair(&mut map, &mut rng);
+    assert!(validate(&map).is_ok());
+  }
+}
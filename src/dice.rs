@@ -0,0 +1,140 @@
+use rand::Rng;
+use serde::Deserialize;
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+/// Dice to roll, written like `2d4+1`: two four-sided dice, plus one. In a
+/// config they are read from that same text
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Dice {
+  pub count: i32,
+  pub sides: i32,
+  pub bonus: i32,
+}
+
+impl Dice {
+  pub fn new(count: i32, sides: i32, bonus: i32) -> Self {
+    Dice {
+      count,
+      sides,
+      bonus,
+    }
+  }
+
+  /// the sum of the dice, plus the bonus
+  pub fn roll<R: Rng>(&self, rng: &mut R) -> i32 {
+    let total: i32 = (0..self.count)
+      .map(|_| rng.gen_range(1, self.sides + 1))
+      .sum();
+    total + self.bonus
+  }
+
+  /// the highest possible roll
+  pub fn max(&self) -> i32 {
+    self.count * self.sides + self.bonus
+  }
+}
+
+impl FromStr for Dice {
+  type Err = String;
+
+  /// read dice like `2d4+1`, `d6`, `1d8-1` or just a number like `3`
+  fn from_str(text: &str) -> Result<Self, Self::Err> {
+    let text = text.trim();
+    let number = |s: &str| {
+      s.parse::<i32>()
+        .map_err(|_| format!("`{}` is not a valid dice roll", text))
+    };
+
+    // split off the bonus, keeping its sign
+    let (dice, bonus) = match text.find(&['+', '-'][..]) {
+      Some(i) if i > 0 => (&text[..i], number(&text[i..])?),
+      _ => (text, 0),
+    };
+
+    match dice.find('d') {
+      Some(i) => {
+        let count = if i == 0 { 1 } else { number(&dice[..i])? };
+        let sides = number(&dice[i + 1..])?;
+        if count < 0 || sides < 1 {
+          return Err(format!("`{}` is not a valid dice roll", text));
+        }
+        Ok(Dice::new(count, sides, bonus))
+      }
+      None => Ok(Dice::new(0, 1, number(dice)? + bonus)),
+    }
+  }
+}
+
+impl TryFrom<String> for Dice {
+  type Error = String;
+
+  fn try_from(text: String) -> Result<Self, Self::Error> {
+    text.parse()
+  }
+}
+
+impl fmt::Display for Dice {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    if self.count == 0 {
+      return write!(f, "{}", self.bonus);
+    }
+    write!(f, "{}d{}", self.count, self.sides)?;
+    if self.bonus != 0 {
+      write!(f, "{:+}", self.bonus)?;
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::config::GameConfig;
+
+  #[test]
+  fn parses_dice() {
+    assert_eq!("2d4+1".parse(), Ok(Dice::new(2, 4, 1)));
+    assert_eq!("d6".parse(), Ok(Dice::new(1, 6, 0)));
+    assert_eq!("1d8-1".parse(), Ok(Dice::new(1, 8, -1)));
+    assert_eq!("-3".parse(), Ok(Dice::new(0, 1, -3)));
+    assert_eq!(" 3d6 ".parse(), Ok(Dice::new(3, 6, 0)));
+  }
+
+  #[test]
+  fn rejects_malformed_dice() {
+    for text in &[
+      "", "d", "2d", "2d0", "xd4", "2d4+", "2d4+x", "2x4", "1d6 + 1",
+    ] {
+      assert!(text.parse::<Dice>().is_err(), "{:?}", text);
+    }
+  }
+
+  #[test]
+  fn writes_what_it_reads() {
+    for text in &["2d4+1", "1d8-1", "1d6", "3", "-3"] {
+      assert_eq!(text.parse::<Dice>().unwrap().to_string(), *text);
+    }
+  }
+
+  #[test]
+  fn rolls_within_range() {
+    let dice = Dice::new(2, 4, 1);
+    assert_eq!(dice.max(), 9);
+    let mut rng = rand::thread_rng();
+    for _ in 0..100 {
+      let roll = dice.roll(&mut rng);
+      assert!(roll >= 3 && roll <= dice.max());
+    }
+  }
+
+  #[test]
+  fn read_from_config() {
+    let config = GameConfig::from_json(r#"{ "orc_damage": "2d3-1" }"#).unwrap();
+    assert_eq!(config.orc_damage, Dice::new(2, 3, -1));
+    assert!(GameConfig::from_json(r#"{ "orc_damage": "2d" }"#).is_err());
+    assert!(GameConfig::from_json(r#"{ "troll_damage": "1d2-5" }"#).is_err());
+  }
+}
@@ -0,0 +1,218 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use crate::map::Map;
+use crate::path::{neighbours, step_cost};
+
+/*
+ * Dijkstra maps, as described on RogueBasin:
+ * http://www.roguebasin.com/index.php?title=The_Incredible_Power_of_Dijkstra_Maps
+ *
+ * Every tile holds the cost of walking from it to the closest goal. To get
+ * to a goal, a monster only has to step onto the neighbour with the lowest
+ * value, so any number of monsters can share the same map. Turning a map
+ * upside down makes monsters flee, and scanning it again lets them run past
+ * the goal to get out of a dead end instead of cowering in a corner.
+ */
+
+const UNREACHABLE: i32 = i32::MAX;
+
+/// The cost of walking from every tile of a map to the closest goal
+pub struct DijkstraMap {
+  width: i32,
+  height: i32,
+  diagonals: bool,
+  values: Vec<i32>,
+}
+
+impl DijkstraMap {
+  /// a map where no goal can be reached
+  pub fn empty(width: i32, height: i32) -> Self {
+    DijkstraMap {
+      width,
+      height,
+      diagonals: true,
+      values: vec![UNREACHABLE; (width * height) as usize],
+    }
+  }
+
+  /// the cost of getting to the closest of the `goals` from every tile
+  pub fn new(map: &Map, goals: &[(i32, i32)], diagonals: bool) -> Self {
+    let mut dijkstra = DijkstraMap {
+      diagonals,
+      ..DijkstraMap::empty(map.width, map.height)
+    };
+    let starts: Vec<_> = goals
+      .iter()
+      .filter(|&&(x, y)| dijkstra.in_bounds(x, y))
+      .map(|&(x, y)| (x, y, 0))
+      .collect();
+    dijkstra.scan(map, &starts);
+    dijkstra
+  }
+
+  /// a map to flee from the goals of this one. Monsters following it run
+  /// away, and take a longer way around rather than getting cornered
+  pub fn flee(&self, map: &Map) -> Self {
+    let mut flee = DijkstraMap::empty(self.width, self.height);
+    flee.diagonals = self.diagonals;
+
+    let mut starts = vec![];
+    for y in 0..self.height {
+      for x in 0..self.width {
+        if let Some(value) = self.value(x, y) {
+          starts.push((x, y, -value * 6 / 5));
+        }
+      }
+    }
+    flee.scan(map, &starts);
+    flee
+  }
+
+  fn in_bounds(&self, x: i32, y: i32) -> bool {
+    x >= 0 && y >= 0 && x < self.width && y < self.height
+  }
+
+  fn index(&self, x: i32, y: i32) -> usize {
+    (x + y * self.width) as usize
+  }
+
+  /// spread the values of `starts` over the map, keeping the lowest cost of every tile
+  fn scan(&mut self, map: &Map, starts: &[(i32, i32, i32)]) {
+    let mut open = BinaryHeap::new();
+    for &(x, y, value) in starts {
+      let i = self.index(x, y);
+      if value < self.values[i] {
+        self.values[i] = value;
+        open.push(Reverse((value, (x, y))));
+      }
+    }
+
+    while let Some(Reverse((value, (x, y)))) = open.pop() {
+      if value > self.values[self.index(x, y)] {
+        // already reached at a lower cost
+        continue;
+      }
+
+      for (nx, ny) in neighbours(x, y, self.diagonals) {
+        if !self.in_bounds(nx, ny) {
+          continue;
+        }
+        if let Some(step) = step_cost(map.tile_at(nx, ny).terrain) {
+          let i = self.index(nx, ny);
+          if value + step < self.values[i] {
+            self.values[i] = value + step;
+            open.push(Reverse((value + step, (nx, ny))));
+          }
+        }
+      }
+    }
+  }
+
+  /// the cost of getting to a goal from (`x`, `y`), `None` if it can't be done
+  pub fn value(&self, x: i32, y: i32) -> Option<i32> {
+    if !self.in_bounds(x, y) {
+      return None;
+    }
+    match self.values[self.index(x, y)] {
+      UNREACHABLE => None,
+      value => Some(value),
+    }
+  }
+
+  /// the best neighbour to step onto from (`x`, `y`), skipping the ones
+  /// that aren't `free`. `None` if every way is worse than staying put
+  pub fn next_step<F>(&self, x: i32, y: i32, free: F) -> Option<(i32, i32)>
+  where
+    F: Fn(i32, i32) -> bool,
+  {
+    let mut best = (self.value(x, y)?, None);
+    for (nx, ny) in neighbours(x, y, self.diagonals) {
+      if let Some(value) = self.value(nx, ny) {
+        if value < best.0 && free(nx, ny) {
+          best = (value, Some((nx, ny)));
+        }
+      }
+    }
+    best.1
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::map::Tile;
+  use crate::terrain::Terrain;
+
+  /// a 7x4 room with a pillar at (3, 2):
+  ///
+  /// #######
+  /// #.....#
+  /// #..#..#
+  /// #######
+  fn room() -> Map {
+    let mut map = Map::solid(7, 4);
+    for x in 1..6 {
+      for y in 1..3 {
+        if (x, y) != (3, 2) {
+          map.set_tile(x, y, Tile::new(Terrain::Floor));
+        }
+      }
+    }
+    map
+  }
+
+  #[test]
+  fn costs_to_the_closest_goal() {
+    let map = room();
+    let dijkstra = DijkstraMap::new(&map, &[(1, 2)], true);
+    let row = |y| (1..6).map(|x| dijkstra.value(x, y)).collect::<Vec<_>>();
+    assert_eq!(row(1), [Some(1), Some(1), Some(2), Some(3), Some(4)]);
+    assert_eq!(row(2), [Some(0), Some(1), None, Some(3), Some(4)]);
+    assert_eq!(dijkstra.value(0, 0), None);
+    assert_eq!(dijkstra.value(-1, 7), None);
+
+    // without diagonals, the way around the pillar is longer
+    let dijkstra = DijkstraMap::new(&map, &[(1, 2)], false);
+    assert_eq!(dijkstra.value(4, 2), Some(5));
+
+    // with two goals, every tile goes to the closer one
+    let dijkstra = DijkstraMap::new(&map, &[(1, 2), (5, 1)], true);
+    assert_eq!(dijkstra.value(4, 2), Some(1));
+    assert_eq!(dijkstra.value(3, 1), Some(2));
+  }
+
+  #[test]
+  fn steps_downhill_onto_free_tiles() {
+    let map = room();
+    let dijkstra = DijkstraMap::new(&map, &[(1, 2)], true);
+    assert_eq!(dijkstra.next_step(5, 2, |_, _| true), Some((4, 2)));
+    assert_eq!(
+      dijkstra.next_step(5, 2, |x, y| (x, y) != (4, 2)),
+      Some((4, 1))
+    );
+    assert_eq!(dijkstra.next_step(3, 1, |_, _| true), Some((2, 1)));
+    assert_eq!(
+      dijkstra.next_step(3, 1, |x, y| (x, y) != (2, 1)),
+      Some((2, 2))
+    );
+
+    // nowhere better to go
+    assert_eq!(dijkstra.next_step(1, 2, |_, _| true), None);
+    assert_eq!(dijkstra.next_step(3, 1, |_, _| false), None);
+    assert_eq!(dijkstra.next_step(0, 0, |_, _| true), None);
+  }
+
+  #[test]
+  fn fleeing_runs_away_from_the_goals() {
+    let map = room();
+    let flee = DijkstraMap::new(&map, &[(1, 2)], true).flee(&map);
+    assert_eq!(flee.next_step(2, 2, |_, _| true), Some((3, 1)));
+    assert_eq!(flee.next_step(3, 1, |_, _| true), Some((4, 1)));
+    assert!(flee.value(5, 2) < flee.value(1, 2));
+    assert_eq!(flee.value(3, 2), None);
+
+    // stuck in the far corner
+    assert_eq!(flee.next_step(5, 2, |_, _| true), None);
+  }
+}
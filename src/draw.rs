@@ -2,7 +2,6 @@ use std::iter::FromIterator;
 use web_sys::CanvasRenderingContext2d;
 
 use crate::colors::{self, Color};
-use crate::config::*;
 
 /// Rendering engine
 /// Named "Tcod" after the rogulelike "tcod" project: https://pypi.org/project/tcod/
@@ -12,6 +11,10 @@ pub struct Tcod {
   fill: Option<Color>,
   stroke: Option<Color>,
   bg: Vec<Vec<Option<Color>>>,
+  // characters describing the background, for renderers that can't show colors
+  glyphs: Vec<Vec<Option<char>>>,
+  // characters that belong to the map tiles, and stay when the other ones are cleared
+  tile_chars: Vec<Vec<Option<(char, Color)>>>,
   chars: Vec<Vec<Option<(char, Color)>>>,
 }
 
@@ -29,15 +32,18 @@ impl Tcod {
       fill: None,
       stroke: None,
       bg: vec![vec![None; h as usize]; w as usize],
+      glyphs: vec![vec![None; h as usize]; w as usize],
+      tile_chars: vec![vec![None; h as usize]; w as usize],
       // fg: vec![vec![None; h as usize]; w as usize],
       chars: vec![vec![None; h as usize]; w as usize],
     }
   }
 
+  /// clear all characters, except the ones that belong to map tiles
   pub fn clear_chars(&mut self) {
     for x in 0..self.w {
       for y in 0..self.h {
-        self.chars[x as usize][y as usize] = None;
+        self.chars[x as usize][y as usize] = self.tile_chars[x as usize][y as usize];
       }
     }
   }
@@ -55,6 +61,12 @@ impl Tcod {
     // self.panel.clear();
     self.fill(color);
     self.rect(0, 0, self.w, self.h);
+    for x in 0..self.w as usize {
+      for y in 0..self.h as usize {
+        self.glyphs[x][y] = None;
+        self.tile_chars[x][y] = None;
+      }
+    }
   }
 
   pub fn put_char_background(&mut self, x: i32, y: i32, color: Color) {
@@ -65,6 +77,19 @@ impl Tcod {
     self.bg[x as usize][y as usize] = Some(color);
   }
 
+  /// set the background color of a map tile, along with the glyph that represents it
+  pub fn put_tile(&mut self, x: i32, y: i32, glyph: char, color: Color) {
+    self.put_char_background(x, y, color);
+    self.glyphs[x as usize][y as usize] = Some(glyph);
+    self.tile_chars[x as usize][y as usize] = None;
+  }
+
+  /// draw a character that is part of a map tile, so that it is kept
+  /// by `clear_chars`. Must come after `put_tile`
+  pub fn put_tile_char(&mut self, x: i32, y: i32, char: char, color: Color) {
+    self.tile_chars[x as usize][y as usize] = Some((char, color));
+  }
+
   pub fn put_char(&mut self, x: i32, y: i32, char: char) {
     // self.fg[x as usize][y as usize] = self.stroke;
     self.chars[x as usize][y as usize] = Some((char, self.stroke.unwrap_or(colors::WHITE)));
@@ -145,17 +170,8 @@ impl Tcod {
         chars[((y * (self.w + 1)) + x) as usize] =
           if let Some((obj, _color)) = self.chars[x as usize][y as usize] {
             obj
-          } else if let Some(wall) = self.bg[x as usize][y as usize] {
-            match wall {
-              colors::BLACK => '█',
-              COLOR_DARK_GROUND => '▒',
-              COLOR_LIGHT_GROUND => ' ',
-              COLOR_DARK_WALL => '▓',
-              COLOR_LIGHT_WALL => '░',
-              _ => ' ',
-            }
           } else {
-            ' '
+            self.glyphs[x as usize][y as usize].unwrap_or(' ')
           };
       }
     }
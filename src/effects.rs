@@ -0,0 +1,231 @@
+use crate::colors::{self, Color};
+
+/*
+ * Status effects: temporary states of a fighter that wear off after a
+ * number of its turns. A fighter has at most one effect of every kind:
+ * getting the same effect again either stacks with it (poison gets
+ * stronger) or refreshes it (the rest last as long as the longest).
+ */
+
+/// The kinds of status effect
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EffectKind {
+  /// loses `potency` hit points every turn
+  Poison,
+  /// moves at random
+  Confusion,
+  /// skips its turns
+  Stun,
+  /// `potency` percent faster
+  Haste,
+  /// `potency` percent slower
+  Slow,
+  /// heals `potency` hit points every turn
+  Regeneration,
+}
+
+const KIND_COUNT: usize = 6;
+
+impl EffectKind {
+  /// how it's said of whoever has it, e.g. "the orc is poisoned"
+  pub fn name(self) -> &'static str {
+    match self {
+      EffectKind::Poison => "poisoned",
+      EffectKind::Confusion => "confused",
+      EffectKind::Stun => "stunned",
+      EffectKind::Haste => "hasted",
+      EffectKind::Slow => "slowed",
+      EffectKind::Regeneration => "regenerating",
+    }
+  }
+
+  /// the icon shown in the side panel
+  pub fn icon(self) -> char {
+    match self {
+      EffectKind::Poison => '!',
+      EffectKind::Confusion => '?',
+      EffectKind::Stun => '*',
+      EffectKind::Haste => '>',
+      EffectKind::Slow => '<',
+      EffectKind::Regeneration => '+',
+    }
+  }
+
+  pub fn color(self) -> Color {
+    match self {
+      EffectKind::Poison => colors::GREEN,
+      EffectKind::Confusion => colors::LIGHT_VIOLET,
+      EffectKind::Stun => colors::YELLOW,
+      EffectKind::Haste => colors::LIGHT_SKY,
+      EffectKind::Slow => colors::LIGHT_BLUE,
+      EffectKind::Regeneration => colors::LIGHT_RED,
+    }
+  }
+
+  /// does getting it again make it stronger? If not, it only lasts longer
+  pub fn stacks(self) -> bool {
+    self == EffectKind::Poison
+  }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Effect {
+  pub kind: EffectKind,
+  /// how many more turns it lasts
+  pub turns: i32,
+  /// how strong it is, its meaning depends on the kind
+  pub potency: i32,
+}
+
+impl Effect {
+  pub fn new(kind: EffectKind, turns: i32, potency: i32) -> Self {
+    Effect {
+      kind,
+      turns,
+      potency,
+    }
+  }
+}
+
+/// The effects on a fighter, at most one of every kind
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Effects {
+  active: [Option<Effect>; KIND_COUNT],
+}
+
+impl Effects {
+  /// add an effect, stacking with or refreshing the one of the same kind.
+  /// Returns true if it wasn't there yet
+  pub fn add(&mut self, effect: Effect) -> bool {
+    match &mut self.active[effect.kind as usize] {
+      Some(current) => {
+        if effect.kind.stacks() {
+          current.potency += effect.potency;
+        } else {
+          current.potency = current.potency.max(effect.potency);
+        }
+        current.turns = current.turns.max(effect.turns);
+        false
+      }
+      slot => {
+        *slot = Some(effect);
+        true
+      }
+    }
+  }
+
+  pub fn get(&self, kind: EffectKind) -> Option<Effect> {
+    self.active[kind as usize]
+  }
+
+  pub fn has(&self, kind: EffectKind) -> bool {
+    self.get(kind).is_some()
+  }
+
+  /// the potency of an effect, 0 if it's not there
+  pub fn potency(&self, kind: EffectKind) -> i32 {
+    self.get(kind).map_or(0, |effect| effect.potency)
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item = &Effect> {
+    self.active.iter().flatten()
+  }
+
+  /// count down a turn. Returns the kinds of the effects that wore off
+  pub fn tick(&mut self) -> Vec<EffectKind> {
+    let mut expired = vec![];
+    for slot in self.active.iter_mut() {
+      if let Some(effect) = slot {
+        effect.turns -= 1;
+        if effect.turns <= 0 {
+          expired.push(effect.kind);
+          *slot = None;
+        }
+      }
+    }
+    expired
+  }
+
+  /// the percentage of its speed a fighter moves at, see `Object::speed_modifier`
+  pub fn speed_modifier(&self) -> i32 {
+    let modifier = 100 + self.potency(EffectKind::Haste) - self.potency(EffectKind::Slow);
+    modifier.max(10)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn adds_new_effects() {
+    let mut effects = Effects::default();
+    assert!(!effects.has(EffectKind::Stun));
+    assert!(effects.add(Effect::new(EffectKind::Stun, 2, 0)));
+    assert!(effects.add(Effect::new(EffectKind::Poison, 3, 1)));
+    assert!(effects.has(EffectKind::Stun) && effects.has(EffectKind::Poison));
+    assert_eq!(effects.iter().count(), 2);
+  }
+
+  #[test]
+  fn poison_stacks() {
+    let mut effects = Effects::default();
+    effects.add(Effect::new(EffectKind::Poison, 4, 1));
+    assert!(!effects.add(Effect::new(EffectKind::Poison, 2, 2)));
+    assert_eq!(
+      effects.get(EffectKind::Poison),
+      Some(Effect::new(EffectKind::Poison, 4, 3))
+    );
+  }
+
+  #[test]
+  fn others_refresh() {
+    let mut effects = Effects::default();
+    effects.add(Effect::new(EffectKind::Regeneration, 3, 2));
+    assert!(!effects.add(Effect::new(EffectKind::Regeneration, 8, 1)));
+    assert_eq!(
+      effects.get(EffectKind::Regeneration),
+      Some(Effect::new(EffectKind::Regeneration, 8, 2))
+    );
+  }
+
+  #[test]
+  fn effects_expire() {
+    let mut effects = Effects::default();
+    effects.add(Effect::new(EffectKind::Stun, 1, 0));
+    effects.add(Effect::new(EffectKind::Confusion, 2, 0));
+
+    assert_eq!(effects.tick(), vec![EffectKind::Stun]);
+    assert_eq!(
+      effects
+        .get(EffectKind::Confusion)
+        .map(|effect| effect.turns),
+      Some(1)
+    );
+    assert_eq!(effects.tick(), vec![EffectKind::Confusion]);
+    assert_eq!(effects.iter().count(), 0);
+    assert!(effects.tick().is_empty());
+  }
+
+  #[test]
+  fn haste_speeds_up() {
+    let mut effects = Effects::default();
+    assert_eq!(effects.speed_modifier(), 100);
+    effects.add(Effect::new(EffectKind::Haste, 2, 50));
+    assert_eq!(effects.speed_modifier(), 150);
+    effects.tick();
+    effects.tick();
+    assert_eq!(effects.speed_modifier(), 100);
+  }
+
+  #[test]
+  fn slow_slows_down_but_never_stops() {
+    let mut effects = Effects::default();
+    effects.add(Effect::new(EffectKind::Slow, 2, 30));
+    assert_eq!(effects.speed_modifier(), 70);
+    effects.add(Effect::new(EffectKind::Haste, 2, 50));
+    assert_eq!(effects.speed_modifier(), 120);
+    effects.add(Effect::new(EffectKind::Slow, 2, 500));
+    assert_eq!(effects.speed_modifier(), 10);
+  }
+}
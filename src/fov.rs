@@ -1,9 +1,10 @@
 use bresenham::Bresenham;
+use serde::Deserialize;
 use std::cmp;
 
-/*
- * Ported from https://github.com/libtcod/libtcod/blob/master/src/libtcod/fov_circular_raycasting.c
- */
+use crate::map::Map;
+use crate::rect::Rect;
+use crate::shadowcasting::{RecursiveShadowcasting, SymmetricShadowcasting};
 
 #[derive(Default, Clone, Copy, Debug)]
 pub struct Cell {
@@ -12,38 +13,190 @@ pub struct Cell {
   pub fov: bool,
 }
 
+/// An algorithm that works out which cells can be seen from a point
+pub trait FovAlgorithm {
+  /// set `Cell::fov` for every cell that can be seen from (`x`, `y`). The
+  /// flags have all been cleared beforehand
+  fn compute(&self, fov: &mut FOV, x: i32, y: i32, max_radius: i32, light_walls: bool);
+}
+
+/// The FOV algorithms to choose from
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+pub enum FovKind {
+  /// casts a ray to every cell on the edge of the view, like libtcod's default
+  Raycasting,
+  /// scans the view octant by octant, following the shadows of the walls
+  Shadowcasting,
+  /// shadowcasting where a floor tile is only seen if it could see back
+  SymmetricShadowcasting,
+}
+
+impl FovKind {
+  pub fn algorithm(self) -> &'static dyn FovAlgorithm {
+    match self {
+      FovKind::Raycasting => &CircularRaycasting,
+      FovKind::Shadowcasting => &RecursiveShadowcasting,
+      FovKind::SymmetricShadowcasting => &SymmetricShadowcasting,
+    }
+  }
+}
+
 #[derive(Debug)]
 pub struct FOV {
   w: i32,
   h: i32,
   nbcells: i32,
+  kind: FovKind,
+  /// the area that the last `compute_fov` could have lit up
+  view: Option<Rect>,
+  /// the cells that came into or went out of view during the last `compute_fov`
+  changed: Vec<(i32, i32)>,
   pub cells: Vec<Cell>, // TEMP
 }
 
+/// iterate over every cell of an area, edges included
+fn cells_in(area: Rect) -> impl Iterator<Item = (i32, i32)> {
+  (area.y1..=area.y2).flat_map(move |y| (area.x1..=area.x2).map(move |x| (x, y)))
+}
+
 impl FOV {
-  pub fn new(w: i32, h: i32) -> Self {
+  pub fn new(w: i32, h: i32, kind: FovKind) -> Self {
     Self {
       w: w,
       h: h,
       nbcells: w * h,
+      kind,
+      view: None,
+      changed: vec![],
       cells: vec![Default::default(); (w * h) as usize],
     }
   }
 
+  /// a FOV map with the same size and transparency as `map`
+  pub fn from_map(map: &Map, kind: FovKind) -> Self {
+    let mut fov = FOV::new(map.width, map.height, kind);
+    for y in 0..map.height {
+      for x in 0..map.width {
+        let tile = map.tile_at(x, y);
+        fov.set(x, y, !tile.block_sight(), !tile.blocked());
+      }
+    }
+    fov
+  }
+
+  pub fn width(&self) -> i32 {
+    self.w
+  }
+
+  pub fn height(&self) -> i32 {
+    self.h
+  }
+
+  pub fn in_bounds(&self, x: i32, y: i32) -> bool {
+    x >= 0 && y >= 0 && x < self.w && y < self.h
+  }
+
+  pub fn is_transparent(&self, x: i32, y: i32) -> bool {
+    self.cells[(x + y * self.w) as usize].transparent
+  }
+
+  pub fn set_in_fov(&mut self, x: i32, y: i32) {
+    self.cells[(x + y * self.w) as usize].fov = true;
+  }
+
   // Why does `walkable` matter?
+  // `fov` is left alone, so that the next `compute_fov` notices if it changes
   pub fn set(&mut self, x: i32, y: i32, transparent: bool, walkable: bool) {
     let mut cell = &mut self.cells[(x + y * self.w) as usize];
     cell.walkable = walkable;
     cell.transparent = transparent;
-    cell.fov = false;
   }
 
   pub fn is_in_fov(&self, x: i32, y: i32) -> bool {
     self.cells[(x + y * self.w) as usize].fov
   }
 
-  /// circular ray casting
+  /// the cells whose visibility changed during the last `compute_fov`
+  pub fn changed(&self) -> &[(i32, i32)] {
+    &self.changed
+  }
+
+  /// the area that can be seen from (`x`, `y`) at most
+  fn view_around(&self, x: i32, y: i32, max_radius: i32) -> Rect {
+    if max_radius > 0 {
+      Rect {
+        x1: cmp::max(0, x - max_radius),
+        y1: cmp::max(0, y - max_radius),
+        x2: cmp::min(self.w - 1, x + max_radius),
+        y2: cmp::min(self.h - 1, y + max_radius),
+      }
+    } else {
+      Rect::new(0, 0, self.w - 1, self.h - 1)
+    }
+  }
+
+  /// work out what can be seen from (`player_x`, `player_y`), with the chosen algorithm.
+  /// Only the cells around the old and the new position are touched
   pub fn compute_fov(&mut self, player_x: i32, player_y: i32, max_radius: i32, light_walls: bool) {
+    let view = self.view_around(player_x, player_y, max_radius);
+    let area = match self.view {
+      Some(old) => Rect {
+        x1: cmp::min(old.x1, view.x1),
+        y1: cmp::min(old.y1, view.y1),
+        x2: cmp::max(old.x2, view.x2),
+        y2: cmp::max(old.y2, view.y2),
+      },
+      None => view,
+    };
+
+    let before: Vec<bool> = cells_in(area).map(|(x, y)| self.is_in_fov(x, y)).collect();
+    if let Some(old) = self.view {
+      for (x, y) in cells_in(old) {
+        self.cells[(x + y * self.w) as usize].fov = false;
+      }
+    }
+
+    self
+      .kind
+      .algorithm()
+      .compute(self, player_x, player_y, max_radius, light_walls);
+
+    self.changed = cells_in(area)
+      .zip(before)
+      .filter(|&((x, y), was_in_fov)| self.is_in_fov(x, y) != was_in_fov)
+      .map(|(cell, _)| cell)
+      .collect();
+    self.view = Some(view);
+  }
+}
+
+/*
+ * Ported from https://github.com/libtcod/libtcod/blob/master/src/libtcod/fov_circular_raycasting.c
+ */
+pub struct CircularRaycasting;
+
+impl FovAlgorithm for CircularRaycasting {
+  fn compute(
+    &self,
+    fov: &mut FOV,
+    player_x: i32,
+    player_y: i32,
+    max_radius: i32,
+    light_walls: bool,
+  ) {
+    fov.compute_raycasting(player_x, player_y, max_radius, light_walls);
+  }
+}
+
+impl FOV {
+  /// circular ray casting
+  fn compute_raycasting(
+    &mut self,
+    player_x: i32,
+    player_y: i32,
+    max_radius: i32,
+    light_walls: bool,
+  ) {
     let mut xmin = 0;
     let mut ymin = 0;
     let mut xmax = self.w;
@@ -56,10 +209,6 @@ impl FOV {
       ymax = cmp::min(self.h, player_y + max_radius + 1);
     }
 
-    for i in 0..self.nbcells {
-      self.cells[i as usize].fov = false;
-    }
-
     let r2 = max_radius * max_radius;
     let mut xo = xmin;
     let mut yo = ymin;
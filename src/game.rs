@@ -1,61 +1,262 @@
 use pcg_rand::{seeds::PcgSeeder, Pcg32Basic};
 use rand::{Rng, SeedableRng};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::mem;
 
-use crate::ai::Ai;
+use crate::ai::{Ai, Chaser, Coward, Guard, Wanderer};
 use crate::colors;
 use crate::config::*;
-use crate::draw::Tcod;
+use crate::dice::Dice;
+use crate::dijkstra::DijkstraMap;
+use crate::draw::{Tcod, TextAlignment};
+use crate::effects::{Effect, EffectKind, Effects};
 use crate::fov::FOV;
-use crate::map::Map;
+use crate::level_file::{self, ParseError};
+use crate::light::{Falloff, Light, LightMap};
+use crate::map::{Map, MapGenerator, Region};
 use crate::mem::mut_two;
-use crate::object::{Fighter, Object};
-use crate::rect::Rect;
-use crate::ui::{render_bar, Messages};
+use crate::object::{Fighter, Object, ACTION_COST};
+use crate::path::{PathFinder, PathOptions};
+use crate::terrain::Terrain;
+use crate::ui::{render_bar, render_effects, Messages};
+use crate::weapon::{Weapon, AXE, SPEAR, SWORD};
 
-fn place_objects<R: Rng>(rng: &mut R, room: &Rect, objects: &mut Vec<Object>) {
-  // choose random number of monsters
-  let num_monsters = rng.gen_range(0, MAX_ROOM_MONSTERS + 1);
+/// returns a value that depends on the dungeon level. The table specifies
+/// what value occurs after each level, default is 0
+fn from_dungeon_level(table: &[Transition], level: i32) -> i32 {
+  table
+    .iter()
+    .rev()
+    .find(|transition| level >= transition.level)
+    .map_or(0, |transition| transition.value)
+}
 
-  for _ in 0..num_monsters {
-    // choose random spot for this monster
-    let x = rng.gen_range(room.x1 + 1, room.x2);
-    let y = rng.gen_range(room.y1 + 1, room.y2);
+/// create a monster from its glyph, made a little tougher on every level
+fn make_monster(kind: char, x: i32, y: i32, level: i32, config: &GameConfig) -> Object {
+  let bonus = level - 1;
 
-    let mut monster = if rng.gen::<f32>() < 0.8 {
-      // 80% chance of getting an orc
+  let mut monster = match kind {
+    'T' => {
+      let mut troll = Object::new(x, y, 'T', colors::DARKER_GREEN, "troll", true);
+      troll.fighter = Some(Fighter {
+        max_hp: 16 + bonus * 3,
+        hp: 16 + bonus * 3,
+        defense: 1 + bonus / 3,
+        accuracy: 1 + bonus / 2,
+        evasion: bonus / 3,
+        damage: Dice {
+          bonus: config.troll_damage.bonus + bonus / 2,
+          ..config.troll_damage
+        },
+        // a troll's blow can knock you out for a turn
+        on_critical: Some(Effect::new(EffectKind::Stun, 1, 0)),
+        effects: Effects::default(),
+        mtype: 2,
+      });
+      troll.ai = Some(Ai::new(6, Box::new(Chaser::default())));
+      troll.speed = 12;
+      troll
+    }
+    _ => {
       // create an orc
       let mut orc = Object::new(x, y, 'o', colors::DESATURATED_GREEN, "orc", true);
       orc.fighter = Some(Fighter {
-        max_hp: 10,
-        hp: 10,
-        defense: 0,
-        power: 3,
+        max_hp: 10 + bonus * 2,
+        hp: 10 + bonus * 2,
+        defense: bonus / 3,
+        accuracy: bonus / 2,
+        evasion: 1 + bonus / 3,
+        damage: Dice {
+          bonus: config.orc_damage.bonus + bonus / 2,
+          ..config.orc_damage
+        },
+        // and orcs have dirty blades
+        on_critical: Some(Effect::new(EffectKind::Poison, 4, 1)),
+        effects: Effects::default(),
         mtype: 1,
       });
-      orc.ai = Some(Ai { speed: 5 });
-
+      orc.ai = Some(Ai::new(9, Box::new(Coward::default())));
       orc
-    } else {
-      let mut troll = Object::new(x, y, 'T', colors::DARKER_GREEN, "troll", true);
-      troll.fighter = Some(Fighter {
-        max_hp: 16,
-        hp: 16,
-        defense: 1,
-        power: 4,
-        mtype: 2,
-      });
-      troll.ai = Some(Ai { speed: 8 });
-      troll
-    };
+    }
+  };
+
+  monster.alive = true;
+  monster
+}
 
-    monster.alive = true;
-    objects.push(monster);
+/// create the object for a legend character (as used by vault templates)
+fn spawn_marker<R: Rng>(
+  rng: &mut R,
+  marker: char,
+  x: i32,
+  y: i32,
+  level: i32,
+  config: &GameConfig,
+) -> Option<Object> {
+  match marker {
+    'o' | 'T' | 'M' => {
+      // random monster, with trolls getting more common the deeper we go
+      let kind = match marker {
+        'M' if rng.gen_range(0, 100) < from_dungeon_level(&config.troll_chance, level) => 'T',
+        'M' => 'o',
+        _ => marker,
+      };
+      let mut monster = make_monster(kind, x, y, level, config);
+      // so that monsters of the same kind don't all act on the same tick
+      monster.energy = rng.gen_range(0, ACTION_COST);
+      Some(monster)
+    }
+    '!' => {
+      // some potions make you faster rather than heal you
+      let (color, name) = if rng.gen_range(0, SPEED_POTION_ODDS) == 0 {
+        (colors::LIGHT_SKY, SPEED_POTION)
+      } else {
+        (colors::VIOLET, HEALING_POTION)
+      };
+      Some(Object::new(x, y, '!', color, name, false))
+    }
+    '?' => Some(Object::new(
+      x,
+      y,
+      '?',
+      colors::LIGHT_YELLOW,
+      CONFUSION_SCROLL,
+      false,
+    )),
+    'k' => Some(Object::new(x, y, 'k', colors::GOLD, KEY, false)),
+    '&' => Some(make_brazier(x, y)),
+    _ => None,
   }
 }
 
+fn place_objects<R: Rng>(
+  rng: &mut R,
+  region: &Region,
+  objects: &mut Vec<Object>,
+  level: i32,
+  config: &GameConfig,
+) {
+  // choose random number of monsters, more of them the deeper we go
+  let max_monsters = from_dungeon_level(&config.max_room_monsters, level);
+  let num_monsters = rng.gen_range(0, max_monsters + 1);
+
+  for _ in 0..num_monsters {
+    // choose random spot for this monster
+    let (x, y) = region.points[rng.gen_range(0, region.points.len())];
+
+    if let Some(mut monster) = spawn_marker(rng, 'M', x, y, level, config) {
+      // some of them roam the level
+      if rng.gen::<f32>() < config.wanderer_chance {
+        if let Some(ai) = monster.ai.as_mut() {
+          ai.behaviour = Box::new(Wanderer::default());
+        }
+      }
+      objects.push(monster);
+    }
+  }
+
+  // some rooms are lit by a brazier
+  if rng.gen::<f32>() < config.brazier_chance {
+    let (x, y) = region.points[rng.gen_range(0, region.points.len())];
+    if !objects.iter().any(|object| object.pos() == (x, y)) {
+      objects.push(make_brazier(x, y));
+    }
+  }
+}
+
+fn make_brazier(x: i32, y: i32) -> Object {
+  let mut brazier = Object::new(x, y, '&', colors::FLAME, "brazier", true);
+  brazier.light = Some(Light::new(6, colors::LIGHT_AMBER, Falloff::Linear));
+  brazier
+}
+
+/// the player is a little quicker than an orc
+const PLAYER_SPEED: i32 = 25;
+
+/// the most ticks a turn can take in turn-based mode, in case the player
+/// has no speed to build up energy with. After that, the player gets its
+/// turn anyway
+const MAX_TICKS_PER_TURN: i32 = 1000;
+
+/// how far guards go from their post to fight
+const GUARD_RANGE: i32 = 4;
+
+const STAIRS_DOWN: &str = "stairs down";
+const STAIRS_UP: &str = "stairs up";
+const KEY: &str = "key";
+const HEALING_POTION: &str = "healing potion";
+const SPEED_POTION: &str = "speed potion";
+const CONFUSION_SCROLL: &str = "scroll of confusion";
+
+/// a healing potion heals this much every turn, for a number of turns
+const POTION_HEAL: i32 = 2;
+const POTION_TURNS: i32 = 8;
+
+/// one potion in this many is a speed potion, which makes you this much
+/// faster (in percent) for a number of turns
+const SPEED_POTION_ODDS: i32 = 3;
+const HASTE_SPEED: i32 = 50;
+const HASTE_TURNS: i32 = 20;
+
+/// wading through shallow water leaves the player soaked, and this much
+/// slower (in percent) for a few turns after getting out
+const SOAKED_SLOW: i32 = 30;
+const SOAKED_TURNS: i32 = 3;
+
+/// how many of their turns the monsters in view stay confused after
+/// reading a scroll of confusion
+const CONFUSION_TURNS: i32 = 8;
+
+/// can the object be picked up?
+fn is_item(object: &Object) -> bool {
+  [KEY, HEALING_POTION, SPEED_POTION, CONFUSION_SCROLL].contains(&object.name.as_str())
+}
+
+/// Places that monsters can head for (or run away from) using Dijkstra maps
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Goal {
+  Player,
+  AwayFromPlayer,
+}
+
+/// Dijkstra maps for every `Goal`, shared by all monsters of the level. A map
+/// is only worked out once a monster heads for its goal
+#[derive(Default)]
+pub struct GoalMaps {
+  pub player: Option<DijkstraMap>,
+  pub away_from_player: Option<DijkstraMap>,
+}
+
+impl GoalMaps {
+  /// the map for a goal, `None` if it hasn't been worked out yet
+  pub fn get(&self, goal: Goal) -> Option<&DijkstraMap> {
+    match goal {
+      Goal::Player => self.player.as_ref(),
+      Goal::AwayFromPlayer => self.away_from_player.as_ref(),
+    }
+  }
+}
+
+fn make_stairs(x: i32, y: i32, down: bool) -> Object {
+  let mut stairs = if down {
+    Object::new(x, y, '>', colors::WHITE, STAIRS_DOWN, false)
+  } else {
+    Object::new(x, y, '<', colors::WHITE, STAIRS_UP, false)
+  };
+  stairs.always_visible = true;
+  stairs
+}
+
+/// A level that the player has left, kept around so it can be revisited
+pub struct Level {
+  pub map: Map,
+  pub objects: Vec<Object>,
+}
+
 pub struct Player {
   pub prev_position: (i32, i32),
-  pub attack_ticks: i32,
+  pub keys: i32,
 }
 
 pub struct Game {
@@ -66,10 +267,31 @@ pub struct Game {
   pub objects: Vec<Object>,
   pub player: Player,
   pub tick: u64,
+  pub fov_recompute: bool,
+  /// what monsters follow to get places, forgotten along with the FOV and
+  /// worked out again when needed
+  pub goal_maps: GoalMaps,
+  /// the buffers monsters search for paths in
+  pathfinder: PathFinder,
+  /// the light on every tile, and the FOV map used to cast it
+  pub lights: LightMap,
+  light_fov: FOV,
+  /// the tiles of the map that give off light, like lava
+  terrain_lights: Vec<(i32, i32, Light)>,
+  /// tiles changed since the light was last computed, which may cast shadows differently
+  light_changed: Vec<(i32, i32)>,
+  /// the whole map needs to be drawn again, e.g. after changing levels
+  pub redraw_map: bool,
+  /// tiles that need to be drawn again, because they changed since the last frame
+  pub dirty_tiles: Vec<(i32, i32)>,
+  pub depth: i32,
+  pub levels: HashMap<i32, Level>,
+  pub config: GameConfig,
+  generator: MapGenerator,
 }
 
 impl Game {
-  pub fn new(seed: u64) -> Self {
+  pub fn new(seed: u64, generator: MapGenerator, config: GameConfig) -> Self {
     // random number generator
     let mut rng = Pcg32Basic::from_seed(PcgSeeder::seed(seed));
 
@@ -80,125 +302,629 @@ impl Game {
       max_hp: 30,
       hp: 30,
       defense: 2,
-      power: 5,
+      accuracy: 2,
+      evasion: 2,
+      damage: config.player_damage,
+      // a good blow knocks a monster out for a turn
+      on_critical: Some(Effect::new(EffectKind::Stun, 1, 0)),
+      effects: Effects::default(),
       mtype: 0,
     });
+    player.speed = PLAYER_SPEED;
+    player.weapon = Some(SWORD);
+    // the player gets the first move
+    player.energy = ACTION_COST;
+    player.light = Some(Light::new(
+      config.torch_radius,
+      config.torch_color,
+      Falloff::Linear,
+    ));
 
     let mut game = Game {
-      map: Map::new(&mut rng),
+      map: Map::new(&mut rng, generator, &config),
       messages: Messages::new(),
-      fov: FOV::new(MAP_WIDTH, MAP_HEIGHT),
+      fov: FOV::new(config.map_width, config.map_height, config.fov_algorithm),
       objects: vec![player],
       rng: rng,
       tick: 0,
       player: Player {
         prev_position: (-1, -1),
-        attack_ticks: 0,
+        keys: 0,
       },
+      fov_recompute: true,
+      goal_maps: GoalMaps::default(),
+      pathfinder: PathFinder::new(config.map_width, config.map_height),
+      lights: LightMap::new(config.map_width, config.map_height),
+      light_fov: FOV::new(config.map_width, config.map_height, config.fov_algorithm),
+      terrain_lights: vec![],
+      light_changed: vec![],
+      redraw_map: true,
+      dirty_tiles: vec![],
+      depth: 1,
+      levels: HashMap::new(),
+      config,
+      generator,
     };
 
-    // populate the FOV map, according to the generated map
-    for y in 0..MAP_HEIGHT {
-      for x in 0..MAP_WIDTH {
-        game.fov.set(
-          x,
-          y,
-          !game.map.tile_at(x, y).block_sight,
-          !game.map.tile_at(x, y).blocked,
-        );
+    game.init_level();
+
+    // a warm welcoming message!
+    game.messages.add(
+      "Welcome stranger! Prepare to perish in the Tombs of the Ancient Kings.",
+      colors::RED,
+    );
+
+    game
+  }
+
+  /// populate the FOV map, according to the current map
+  fn init_fov(&mut self) {
+    self.fov = FOV::from_map(&self.map, self.config.fov_algorithm);
+    self.fov_recompute = true;
+    self.lights = LightMap::new(self.map.width, self.map.height);
+    self.light_fov = FOV::from_map(&self.map, self.config.fov_algorithm);
+    self.pathfinder = PathFinder::new(self.map.width, self.map.height);
+    self.light_changed.clear();
+    self.terrain_lights.clear();
+    for y in 0..self.map.height {
+      for x in 0..self.map.width {
+        if let Some(light) = self.map.tile_at(x, y).terrain.light() {
+          self.terrain_lights.push((x, y, light));
+        }
       }
     }
+    self.redraw_map = true;
+    self.dirty_tiles.clear();
+  }
 
-    // populate rooms with objects
-    for i in 0..game.map.rooms.len() {
-      let room = &game.map.rooms[i];
+  /// set up the FOV and objects for a freshly generated `map`
+  fn init_level(&mut self) {
+    self.init_fov();
 
-      if i == 0 {
-        let (x, y) = room.center();
-        game.objects[PLAYER].set_pos(x, y);
+    // populate rooms (or other spawn regions) with objects. Vaults come with
+    // their own, and don't get the stairs either
+    let spawn_regions: Vec<usize> = (0..self.map.regions.len())
+      .filter(|&i| !self.map.regions[i].vault)
+      .collect();
+    for (n, &i) in spawn_regions.iter().enumerate() {
+      let region = &self.map.regions[i];
+
+      if n == 0 {
+        let (x, y) = region.center;
+        self.objects[PLAYER].set_pos(x, y);
+
+        // stairs back up, right where the player arrives
+        if self.depth > 1 {
+          self.objects.push(make_stairs(x, y, false));
+        }
       } else {
         // add enemies/objects
-        place_objects(&mut game.rng, room, &mut game.objects);
+        place_objects(
+          &mut self.rng,
+          region,
+          &mut self.objects,
+          self.depth,
+          &self.config,
+        );
       }
     }
 
-    // a warm welcoming message!
-    game.messages.add(
-      "Welcome stranger! Prepare to perish in the Tombs of the Ancient Kings.",
-      colors::RED,
+    // objects placed by hand, e.g. by vaults
+    for i in 0..self.map.spawns.len() {
+      let (x, y, marker) = self.map.spawns[i];
+      if let Some(mut object) = spawn_marker(&mut self.rng, marker, x, y, self.depth, &self.config)
+      {
+        // monsters placed by hand guard their spot
+        if let Some(ai) = object.ai.as_mut() {
+          ai.behaviour = Box::new(Guard::new((x, y), GUARD_RANGE));
+        }
+        self.objects.push(object);
+      }
+    }
+
+    // create stairs at the center of the last room
+    if let Some(&i) = spawn_regions.last() {
+      let (x, y) = self.map.regions[i].center;
+      self.objects.push(make_stairs(x, y, true));
+    }
+
+    // with nowhere to spawn (e.g. only vaults), start on any open ground
+    // rather than inside the rock, with the ways up and down right there
+    if spawn_regions.is_empty() {
+      let (x, y) = (0..self.map.height)
+        .flat_map(|y| (0..self.map.width).map(move |x| (x, y)))
+        .find(|&(x, y)| !self.map.tile_at(x, y).blocked())
+        .expect("the level has no open ground to start on");
+      self.objects[PLAYER].set_pos(x, y);
+      if self.depth > 1 {
+        self.objects.push(make_stairs(x, y, false));
+      }
+      self.objects.push(make_stairs(x, y, true));
+    }
+  }
+
+  /// replace the current level with one read from a level file
+  pub fn load_level(&mut self, text: &str) -> Result<(), ParseError> {
+    let (max_width, max_height) = (self.config.screen_width, self.config.panel_y());
+    self.map = level_file::parse(text, max_width, max_height)?;
+    self.objects.truncate(PLAYER + 1);
+    self.objects[PLAYER].attacking = None;
+    self.init_fov();
+
+    // the player starts at `@`, or like on any other level without one
+    let start = self
+      .map
+      .spawns
+      .iter()
+      .find(|&&(_, _, marker)| marker == '@')
+      .map(|&(x, y, _)| (x, y))
+      .or_else(|| self.map.regions.first().map(|region| region.center));
+    if let Some((x, y)) = start {
+      self.objects[PLAYER].set_pos(x, y);
+    }
+
+    for i in 0..self.map.spawns.len() {
+      let (x, y, marker) = self.map.spawns[i];
+      let object = match marker {
+        '@' => None,
+        '<' | '>' => Some(make_stairs(x, y, marker == '>')),
+        _ => spawn_marker(&mut self.rng, marker, x, y, self.depth, &self.config),
+      };
+      self.objects.extend(object);
+    }
+
+    Ok(())
+  }
+
+  /// write the current level to a level file, with the objects that are on it
+  pub fn save_level(&self) -> String {
+    let spawns: Vec<_> = self
+      .objects
+      .iter()
+      .filter(|object| level_file::OBJECT_MARKERS.contains(&object.glyph()))
+      .map(|object| (object.x, object.y, object.glyph()))
+      .collect();
+    level_file::write(&self.map, &spawns)
+  }
+
+  /// go down to the next level
+  pub fn next_level(&mut self) {
+    if !self.levels.contains_key(&(self.depth + 1)) {
+      self.messages.add(
+        "You take a moment to rest, and recover your strength.",
+        colors::VIOLET,
+      );
+      let heal_hp = self.objects[PLAYER].fighter.map_or(0, |f| f.max_hp / 2);
+      self.objects[PLAYER].heal(heal_hp);
+
+      self.messages.add(
+        "After a rare moment of peace, you descend deeper into the heart of the dungeon...",
+        colors::RED,
+      );
+    } else {
+      self
+        .messages
+        .add("You descend the stairs again.", colors::RED);
+    }
+
+    self.change_level(self.depth + 1);
+  }
+
+  /// go back up to the previous level
+  pub fn previous_level(&mut self) {
+    self.messages.add(
+      format!("You climb back up to level {}.", self.depth - 1),
+      colors::VIOLET,
     );
+    self.change_level(self.depth - 1);
+  }
 
-    game
+  /// store the current level away and switch to the one at `depth`,
+  /// restoring it exactly as it was left if it was visited before
+  fn change_level(&mut self, depth: i32) {
+    let going_down = depth > self.depth;
+
+    // everything except the player stays behind on the old level, and so
+    // does a swing in progress
+    let objects = self.objects.split_off(PLAYER + 1);
+    self.objects[PLAYER].attacking = None;
+    let (new_map, restored_objects) = match self.levels.remove(&depth) {
+      Some(level) => (level.map, Some(level.objects)),
+      None => (Map::new(&mut self.rng, self.generator, &self.config), None),
+    };
+    let map = mem::replace(&mut self.map, new_map);
+    self.levels.insert(self.depth, Level { map, objects });
+    self.depth = depth;
+
+    match restored_objects {
+      Some(objects) => {
+        self.objects.extend(objects);
+        self.init_fov();
+
+        // arrive on the staircase leading back where we came from
+        let stairs = if going_down { STAIRS_UP } else { STAIRS_DOWN };
+        if let Some(pos) = self
+          .objects
+          .iter()
+          .find(|o| o.name == stairs)
+          .map(Object::pos)
+        {
+          self.objects[PLAYER].set_pos(pos.0, pos.1);
+        }
+      }
+      None => self.init_level(),
+    }
+  }
+
+  /// is the player standing on an object with this name?
+  fn player_on(&self, name: &str) -> bool {
+    self
+      .objects
+      .iter()
+      .any(|object| object.pos() == self.objects[PLAYER].pos() && object.name == name)
+  }
+
+  /// work out the map for a goal, unless it already is
+  fn update_goal_map(&mut self, goal: Goal) {
+    let diagonals = self.config.path_diagonals;
+    let (map, objects) = (&self.map, &self.objects);
+    let to_player = || DijkstraMap::new(map, &[objects[PLAYER].pos()], diagonals);
+
+    let maps = &mut self.goal_maps;
+    match goal {
+      Goal::Player => {
+        maps.player.get_or_insert_with(to_player);
+      }
+      Goal::AwayFromPlayer => {
+        // fleeing works from the way to the player
+        let player = maps.player.get_or_insert_with(to_player);
+        maps
+          .away_from_player
+          .get_or_insert_with(|| player.flee(map));
+      }
+    }
+  }
+
+  /// move an object one step closer to a goal, around other monsters.
+  /// Returns false if it can't get any closer
+  pub fn move_to_goal(&mut self, id: usize, goal: Goal) -> bool {
+    let (x, y) = self.objects[id].pos();
+    self.update_goal_map(goal);
+    let step = self.goal_maps.get(goal).and_then(|goal_map| {
+      goal_map.next_step(x, y, |nx, ny| self.blocking_object_at(nx, ny).is_none())
+    });
+    match step {
+      Some((nx, ny)) => {
+        self.move_by(id, nx - x, ny - y);
+        true
+      }
+      None => false,
+    }
   }
 
+  /// every light on the level: carried by objects, or given off by tiles
+  fn light_sources(&self) -> Vec<(i32, i32, Light)> {
+    let mut sources: Vec<_> = self
+      .objects
+      .iter()
+      .filter_map(|object| object.light.map(|light| (object.x, object.y, light)))
+      .collect();
+    sources.extend_from_slice(&self.terrain_lights);
+    sources
+  }
+
+  /// can the player see the tile? It has to be in view, and lit
+  pub fn can_see(&self, x: i32, y: i32) -> bool {
+    self.fov.is_in_fov(x, y) && self.lights.is_lit(x, y)
+  }
+
+  /// let time pass in real time mode, and catch up with what changed
   pub fn update(&mut self) {
+    self.refresh_view();
+    if !self.config.turn_based {
+      self.run_tick();
+    }
+  }
+
+  /// after the player spent its turn in turn-based mode, the rest of the
+  /// world gets to act until it's the player's turn again
+  fn end_turn(&mut self) {
+    // so that monsters go after the player where it is now
+    self.refresh_view();
+    for _ in 0..MAX_TICKS_PER_TURN {
+      if !self.objects[PLAYER].alive || self.objects[PLAYER].ready() {
+        break;
+      }
+      self.run_tick();
+    }
+
+    // rather than waiting for the player forever
+    let player = &mut self.objects[PLAYER];
+    if player.alive && !player.ready() {
+      player.energy = ACTION_COST;
+    }
+  }
+
+  /// work out what the player sees, and what monsters need to find it
+  fn refresh_view(&mut self) {
     // recompute FOV if needed (the player moved or something)
-    if self.player.prev_position != self.objects[PLAYER].pos() {
+    if self.fov_recompute || self.player.prev_position != self.objects[PLAYER].pos() {
       let (x, y) = self.objects[PLAYER].pos();
 
-      self.fov.compute_fov(x, y, TORCH_RADIUS, FOV_LIGHT_WALLS);
+      let (radius, light_walls) = (self.config.sight_radius, self.config.fov_light_walls);
+      self.fov.compute_fov(x, y, radius, light_walls);
+      self.fov_recompute = false;
+
+      // only the cells that came into or went out of view need drawing again
+      self.dirty_tiles.extend_from_slice(self.fov.changed());
+
+      // the goals may have moved, so the maps are worked out again when needed
+      self.goal_maps = GoalMaps::default();
     }
     self.player.prev_position = self.objects[PLAYER].pos();
 
-    // let monsters take their turn
+    // light up the map again around the lights that moved, or where the map changed
+    let sources = self.light_sources();
+    let ambient = self.config.ambient_light;
+    self
+      .lights
+      .compute(&mut self.light_fov, &sources, ambient, &self.light_changed);
+    self.light_changed.clear();
+    self.dirty_tiles.extend_from_slice(self.lights.changed());
+
+    for &(x, y) in &self.dirty_tiles {
+      if self.fov.is_in_fov(x, y) && self.lights.is_lit(x, y) {
+        // since it can be seen, explore it
+        self.map.set_explored(x, y);
+      }
+    }
+  }
+
+  /// let a tick of time pass: everyone builds up energy, and the monsters
+  /// that have enough of it act, the ones with the most energy first
+  fn run_tick(&mut self) {
+    // the player builds up energy too, but doesn't keep more than a turn's
+    // worth while waiting for input
+    let player = &mut self.objects[PLAYER];
+    player.gain_energy();
+    player.energy = player.energy.min(ACTION_COST);
+
+    // let monsters take their turn, as often as their energy allows
     if self.objects[PLAYER].alive {
+      let mut queue = BinaryHeap::new();
       for id in 1..self.objects.len() {
-        // TODO: how to achieve this without Copy?
-        if let Some(ai) = self.objects[id].ai {
-          ai.action(id, self);
+        if self.objects[id].ai.is_some() {
+          self.objects[id].gain_energy();
+          if self.objects[id].ready() {
+            queue.push((self.objects[id].energy, Reverse(id)));
+          }
         }
       }
-    }
 
-    // player's weapon attacks monsters
-    if let Some((ax, ay)) = self.objects[PLAYER].attacking {
-      let (px, py) = self.objects[PLAYER].pos();
-      for id in 1..self.objects.len() {
-        if self.objects[id].alive {
-          let (x, y) = self.objects[id].pos();
-          let (dx, dy) = (px + ax - x, py + ay - y);
-
-          if dx == 0 && dy == 0 {
-            // if (dx == 0 && dy.abs() <= 1) || (dx.abs() <= 1 && dy == 0) {
-            let (target, source) = mut_two(id, PLAYER, &mut self.objects);
-            source.attack(target, &mut self.messages);
+      while let Some((_, Reverse(id))) = queue.pop() {
+        // the AI is taken out of the monster while it acts, so that it can
+        // change the game. It's gone for good if the monster died meanwhile
+        if let Some(mut ai) = self.objects[id].ai.take() {
+          self.objects[id].energy -= ACTION_COST;
+
+          // a stunned monster loses its turn, and poison may finish it off
+          let stunned = self.objects[id].has_effect(EffectKind::Stun);
+          self.objects[id].tick_effects(&mut self.messages);
+          if self.objects[id].alive && !stunned {
+            ai.action(id, self);
+          }
+          if self.objects[id].alive {
+            self.objects[id].ai = Some(ai);
+            if self.objects[id].ready() {
+              queue.push((self.objects[id].energy, Reverse(id)));
+            }
           }
         }
       }
+    }
 
-      self.player.attack_ticks += 1;
-      if self.player.attack_ticks >= 5 {
-        self.player.attack_ticks = 0;
-        self.objects[PLAYER].stop_attacking();
+    // player's weapon attacks the monsters in the way of its swing, once each
+    let swing = self.objects[PLAYER].swing_tiles(&self.map);
+    for id in 1..self.objects.len() {
+      let already_hit = match &self.objects[PLAYER].attacking {
+        Some(attacking) => attacking.hit.contains(&id),
+        None => true,
+      };
+      if !already_hit && self.objects[id].alive && swing.contains(&self.objects[id].pos()) {
+        let (target, source) = mut_two(id, PLAYER, &mut self.objects);
+        source.attack(target, &mut self.rng, &mut self.messages);
+        if let Some(attacking) = source.attacking.as_mut() {
+          attacking.hit.push(id);
+        }
       }
     }
+    self.objects[PLAYER].update_swing();
 
     self.tick += 1;
   }
 
   pub fn move_by(&mut self, id: usize, dx: i32, dy: i32) {
     let (x, y) = self.objects[id].pos();
-    if !self.is_blocked(x + dx, y + dy) {
-      self.objects[id].move_by(dx, dy);
+    let (x, y) = (x + dx, y + dy);
+
+    match self.map.tile_at(x, y).terrain {
+      // bumping into a door opens it
+      Terrain::ClosedDoor => self.set_terrain(x, y, Terrain::OpenDoor),
+      Terrain::LockedDoor if id == PLAYER => self.unlock_door(x, y),
+      _ => {
+        if !self.is_blocked(x, y) {
+          // rough terrain takes longer to cross
+          let cost = self.map.tile_at(x, y).terrain.move_cost().unwrap_or(1);
+          self.objects[id].energy -= (cost - 1) * ACTION_COST;
+          self.objects[id].move_by(dx, dy);
+          if id == PLAYER {
+            if self.map.tile_at(x, y).terrain == Terrain::ShallowWater {
+              let soaked = Effect::new(EffectKind::Slow, SOAKED_TURNS, SOAKED_SLOW);
+              self.objects[PLAYER].add_effect(soaked, &mut self.messages);
+            }
+            self.pick_up_items();
+          }
+        }
+      }
+    }
+  }
+
+  /// change a tile of the map, keeping the FOV map in sync
+  pub fn set_terrain(&mut self, x: i32, y: i32, terrain: Terrain) {
+    self.map.set_terrain(x, y, terrain);
+    self
+      .fov
+      .set(x, y, terrain.is_transparent(), terrain.is_walkable());
+    self
+      .light_fov
+      .set(x, y, terrain.is_transparent(), terrain.is_walkable());
+    self.fov_recompute = true;
+    self.light_changed.push((x, y));
+    self.dirty_tiles.push((x, y));
+
+    self
+      .terrain_lights
+      .retain(|&(lx, ly, _)| (lx, ly) != (x, y));
+    if let Some(light) = terrain.light() {
+      self.terrain_lights.push((x, y, light));
+    }
+  }
+
+  fn unlock_door(&mut self, x: i32, y: i32) {
+    if self.player.keys > 0 {
+      self.player.keys -= 1;
+      self.set_terrain(x, y, Terrain::OpenDoor);
+      self
+        .messages
+        .add("You unlock the door.", colors::LIGHT_GREEN);
+    } else {
+      self.messages.add("The door is locked.", colors::LIGHT_GREY);
+    }
+  }
+
+  /// close the open doors next to the player, unless something is in the way
+  pub fn close_doors(&mut self) {
+    let (px, py) = self.objects[PLAYER].pos();
+    for &(x, y) in &[(px, py - 1), (px, py + 1), (px - 1, py), (px + 1, py)] {
+      let occupied = self.objects.iter().any(|object| object.pos() == (x, y));
+      if self.map.tile_at(x, y).terrain == Terrain::OpenDoor && !occupied {
+        self.set_terrain(x, y, Terrain::ClosedDoor);
+      }
+    }
+  }
+
+  fn pick_up_items(&mut self) {
+    let (x, y) = self.objects[PLAYER].pos();
+    while let Some(id) = self
+      .objects
+      .iter()
+      .position(|object| object.pos() == (x, y) && is_item(object))
+    {
+      // potions are drunk and scrolls read on the spot
+      let item = self.objects.remove(id);
+      if let Some(swing) = self.objects[PLAYER].attacking.as_mut() {
+        swing.forget(id);
+      }
+      match item.name.as_str() {
+        KEY => {
+          self.player.keys += 1;
+          self.messages.add("You picked up a key.", colors::GOLD);
+        }
+        SPEED_POTION => {
+          self
+            .messages
+            .add("You drink the speed potion.", colors::LIGHT_SKY);
+          let haste = Effect::new(EffectKind::Haste, HASTE_TURNS, HASTE_SPEED);
+          self.objects[PLAYER].add_effect(haste, &mut self.messages);
+        }
+        CONFUSION_SCROLL => self.read_confusion_scroll(),
+        _ => {
+          self
+            .messages
+            .add("You drink the healing potion.", colors::VIOLET);
+          let regeneration = Effect::new(EffectKind::Regeneration, POTION_TURNS, POTION_HEAL);
+          self.objects[PLAYER].add_effect(regeneration, &mut self.messages);
+        }
+      }
+    }
+  }
+
+  /// confuse every monster the player can see
+  fn read_confusion_scroll(&mut self) {
+    self
+      .messages
+      .add("You read the scroll of confusion.", colors::LIGHT_YELLOW);
+    let targets: Vec<_> = (0..self.objects.len())
+      .filter(|&id| {
+        let object = &self.objects[id];
+        id != PLAYER && object.alive && object.ai.is_some() && self.can_see(object.x, object.y)
+      })
+      .collect();
+    if targets.is_empty() {
+      self
+        .messages
+        .add("The words echo through an empty room.", colors::LIGHT_GREY);
+    }
+
+    let confusion = Effect::new(EffectKind::Confusion, CONFUSION_TURNS, 0);
+    for id in targets {
+      self.objects[id].add_effect(confusion, &mut self.messages);
     }
   }
 
   pub fn is_blocked(&self, x: i32, y: i32) -> bool {
     // first test the map tile
-    if self.map.tile_at(x, y).blocked {
+    if self.map.tile_at(x, y).blocked() {
       return true;
     }
 
     // now check for any blocking objects
+    self.blocking_object_at(x, y).is_some()
+  }
+
+  /// the index of the object blocking the tile, if there is one
+  pub fn blocking_object_at(&self, x: i32, y: i32) -> Option<usize> {
     self
       .objects
       .iter()
-      .any(|object| object.blocks && object.pos() == (x, y))
+      .position(|object| object.blocks && object.pos() == (x, y))
   }
 
-  // Naive method to move Object towards position
-  // TODO: use path-finding (e.g. A*)
+  /// move an object one step along the best path to a position, going
+  /// around walls and (if it can) other monsters
   pub fn move_towards(&mut self, id: usize, target_x: i32, target_y: i32) {
+    let (x, y) = self.objects[id].pos();
+    let options = PathOptions {
+      diagonals: self.config.path_diagonals,
+      max_nodes: self.config.path_max_nodes,
+    };
+    let crowd_cost = self.config.path_crowd_cost;
+    let occupied: Vec<_> = self
+      .objects
+      .iter()
+      .enumerate()
+      .filter(|&(other, object)| other != id && object.blocks)
+      .map(|(_, object)| object.pos())
+      .collect();
+
+    let path =
+      self
+        .pathfinder
+        .find_path(&self.map, (x, y), (target_x, target_y), options, |x, y| {
+          if occupied.contains(&(x, y)) {
+            crowd_cost
+          } else {
+            0
+          }
+        });
+    match path {
+      Some(path) => self.move_by(id, path[0].0 - x, path[0].1 - y),
+      None => self.step_towards(id, target_x, target_y),
+    }
+  }
+
+  // Naive method to move Object towards position, when there's no path
+  fn step_towards(&mut self, id: usize, target_x: i32, target_y: i32) {
     // vector from this object to the target, and distance
     let dx = target_x - self.objects[id].x;
     let dy = target_y - self.objects[id].y;
@@ -211,25 +937,83 @@ impl Game {
     self.move_by(id, dx, dy);
   }
 
+  /// act on a key press. Returns true if the player spent its turn on it.
+  /// A stunned player's turn goes by whatever the key is
   pub fn handle_keys(&mut self, key_code: i32) -> bool {
-    if !self.objects[PLAYER].alive {
+    if !self.objects[PLAYER].alive || !self.objects[PLAYER].ready() {
       return false;
     };
 
+    if self.objects[PLAYER].has_effect(EffectKind::Stun) {
+      self
+        .messages
+        .add("You are stunned, and can't act!", colors::YELLOW);
+    } else if !self.player_action(key_code) {
+      return false;
+    }
+
+    // any action takes a turn, on top of what moving onto rough terrain costs
+    self.objects[PLAYER].energy -= ACTION_COST;
+    self.objects[PLAYER].tick_effects(&mut self.messages);
+    if self.config.turn_based {
+      self.end_turn();
+    }
+    true
+  }
+
+  /// do what the key asks for. Returns false if it doesn't do anything
+  fn player_action(&mut self, key_code: i32) -> bool {
     match key_code {
+      // arrow keys: swing the weapon, once it's ready again
+      37..=40 if !self.objects[PLAYER].can_attack() => return false,
       38 => self.objects[PLAYER].start_attacking(0, -1),
       40 => self.objects[PLAYER].start_attacking(0, 1),
       37 => self.objects[PLAYER].start_attacking(-1, 0),
       39 => self.objects[PLAYER].start_attacking(1, 0),
 
-      87 => self.move_by(PLAYER, 0, -1),
-      83 => self.move_by(PLAYER, 0, 1),
-      65 => self.move_by(PLAYER, -1, 0),
-      68 => self.move_by(PLAYER, 1, 0),
-      _ => {}
+      87 => self.move_player(0, -1),
+      83 => self.move_player(0, 1),
+      65 => self.move_player(-1, 0),
+      68 => self.move_player(1, 0),
+
+      67 => self.close_doors(),
+
+      // number keys: switch weapons, but not in the middle of a swing
+      49..=51 if self.objects[PLAYER].attacking.is_some() => return false,
+      49 => self.wield(SWORD),
+      50 => self.wield(SPEAR),
+      51 => self.wield(AXE),
+
+      // space: wait a turn
+      32 => {}
+
+      // '>' key: go down stairs, if the player is on them
+      190 if self.player_on(STAIRS_DOWN) => self.next_level(),
+      // '<' key: go up stairs
+      188 if self.player_on(STAIRS_UP) => self.previous_level(),
+      _ => return false,
     }
+    true
+  }
+
+  /// swap the player's weapon for another one
+  fn wield(&mut self, weapon: Weapon) {
+    self.objects[PLAYER].weapon = Some(weapon);
+    self.messages.add(
+      format!("You wield the {}.", weapon.name),
+      colors::LIGHT_GREY,
+    );
+  }
 
-    false
+  /// move the player, or stumble somewhere else when it's confused
+  fn move_player(&mut self, dx: i32, dy: i32) {
+    if self.objects[PLAYER].has_effect(EffectKind::Confusion) {
+      let dx = self.rng.gen_range(-1, 2);
+      let dy = self.rng.gen_range(-1, 2);
+      self.move_by(PLAYER, dx, dy);
+    } else {
+      self.move_by(PLAYER, dx, dy);
+    }
   }
 
   // pub fn handle_keys(&mut self, key_codes: &[i32]) {
@@ -240,45 +1024,60 @@ impl Game {
   //   }
   // }
 
-  pub fn render(&mut self, tcod: &mut Tcod, mouse: (i32, i32)) {
-    tcod.background(colors::BLACK);
-    tcod.clear_chars();
+  /// draw a single map tile, if it has been explored
+  fn draw_tile(&self, tcod: &mut Tcod, x: i32, y: i32) {
+    // show explored tiles only (any visible tile is explored already)
+    if !self.map.tile_at(x, y).explored {
+      return;
+    }
 
-    // go through all tiles, and set their background color
-    for y in 0..MAP_HEIGHT {
-      for x in 0..MAP_WIDTH {
-        let visible = self.fov.is_in_fov(x, y);
+    // tiles that can be seen are shaded by the light on them
+    let terrain = self.map.tile_at(x, y).terrain;
+    let palette = &self.config.palette;
+    let color = if self.can_see(x, y) {
+      let (dark, lit) = (terrain.color(false, palette), terrain.color(true, palette));
+      self.lights.shade(x, y, dark, lit)
+    } else {
+      terrain.color(false, palette)
+    };
+    tcod.put_tile(x, y, terrain.glyph(), color);
 
-        let mut explored = self.map.tile_at(x, y).explored;
+    // doors are important enough to draw in every render mode
+    if terrain.is_door() {
+      tcod.put_tile_char(x, y, terrain.glyph(), colors::BLACK);
+    }
+  }
 
-        if visible {
-          // since it's visible, explore it
-          explored = true;
-          // TODO: shouldn't this be in `update`?
-          self.map.set_explored(x, y);
-        }
+  pub fn render(&mut self, tcod: &mut Tcod, mouse: (i32, i32)) {
+    if self.redraw_map {
+      tcod.background(colors::BLACK);
 
-        if explored {
-          let wall = self.map.tile_at(x, y).block_sight;
-          let color = match (visible, wall) {
-            // outside of field of view:
-            (false, true) => COLOR_DARK_WALL,
-            (false, false) => COLOR_DARK_GROUND,
-            // inside fov:
-            (true, true) => COLOR_LIGHT_WALL,
-            (true, false) => COLOR_LIGHT_GROUND,
-          };
-
-          // show explored tiles only (any visible tile is explored already)
-          tcod.put_char_background(x, y, color);
+      // go through all tiles, and set their background color
+      for y in 0..self.map.height {
+        for x in 0..self.map.width {
+          self.draw_tile(tcod, x, y);
         }
       }
+      self.redraw_map = false;
+      self.dirty_tiles.clear();
+    } else {
+      // only draw the tiles that changed since the last frame
+      for (x, y) in mem::take(&mut self.dirty_tiles) {
+        self.draw_tile(tcod, x, y);
+      }
+
+      // the panel is drawn from scratch every time
+      tcod.fill(colors::BLACK);
+      tcod.rect(0, self.config.panel_y(), tcod.w, self.config.panel_height);
     }
+    tcod.clear_chars();
 
     let mut to_draw: Vec<_> = self
       .objects
       .iter()
-      .filter(|o| self.fov.is_in_fov(o.x, o.y))
+      .filter(|o| {
+        self.can_see(o.x, o.y) || (o.always_visible && self.map.tile_at(o.x, o.y).explored)
+      })
       .collect();
 
     // sort so that non-blocking objects come first
@@ -293,7 +1092,7 @@ impl Game {
 
     // draw all objects in the list
     for object in to_draw {
-      object.draw(tcod);
+      object.draw(tcod, &self.map);
     }
 
     // // prepare to render the GUI panel
@@ -301,29 +1100,49 @@ impl Game {
     // tcod.panel.clear();
 
     // show the player's stats
+    let panel_y = self.config.panel_y();
     let hp = self.objects[PLAYER].fighter.map_or(0, |f| f.hp);
     let max_hp = self.objects[PLAYER].fighter.map_or(0, |f| f.max_hp);
     render_bar(
       tcod,
       1,
-      1,
-      BAR_WIDTH,
+      panel_y + 1,
+      self.config.bar_width,
       "HP",
       hp,
       max_hp,
       colors::LIGHT_RED,
       colors::DARKER_RED,
     );
+    if let Some(fighter) = self.objects[PLAYER].fighter {
+      render_effects(tcod, 1, panel_y + 2, &fighter.effects);
+    }
 
-    self.messages.draw(tcod);
+    tcod.stroke(colors::LIGHT_GREY);
+    tcod.print_ex(
+      &format!("Dungeon level: {}", self.depth),
+      1,
+      panel_y + 3,
+      TextAlignment::Left,
+    );
+    if self.player.keys > 0 {
+      tcod.print_ex(
+        &format!("Keys: {}", self.player.keys),
+        1,
+        panel_y + 4,
+        TextAlignment::Left,
+      );
+    }
+
+    self.messages.draw(tcod, &self.config);
 
     // display names of objects under the mouse
     tcod.stroke(colors::LIGHT_GREY);
     tcod.print_rect(
       &self.get_names_at(mouse),
       1,
-      PANEL_Y + PANEL_HEIGHT - 2,
-      BAR_WIDTH,
+      panel_y + self.config.panel_height - 2,
+      self.config.bar_width,
       0,
     );
   }
@@ -333,10 +1152,64 @@ impl Game {
     let names = self
       .objects
       .iter()
-      .filter(|obj| obj.pos() == (x, y) && self.fov.is_in_fov(obj.x, obj.y))
-      .map(|obj| obj.name.clone())
+      .filter(|obj| obj.pos() == (x, y) && self.can_see(obj.x, obj.y))
+      .map(|obj| match obj.ai.as_ref().and_then(Ai::status) {
+        // say what monsters are up to
+        Some(status) => format!("{} ({})", obj.name, status),
+        None => obj.name.clone(),
+      })
       .collect::<Vec<_>>();
 
     names.join(", ") // join the names, separated by commas
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn starts_on_open_ground_without_spawn_regions() {
+    // no rooms at all, so the only regions are vaults
+    let config = GameConfig {
+      max_rooms: 0,
+      ..GameConfig::default()
+    };
+    for seed in 0..5 {
+      let game = Game::new(seed, MapGenerator::Rooms, config.clone());
+      let (x, y) = game.objects[PLAYER].pos();
+      assert!(!game.map.tile_at(x, y).blocked());
+      assert!(game.objects.iter().any(|object| object.name == STAIRS_DOWN));
+    }
+  }
+
+  #[test]
+  fn a_standstill_doesnt_freeze_the_turn() {
+    let mut game = Game::new(0, MapGenerator::Rooms, GameConfig::default());
+    game.objects.truncate(PLAYER + 1);
+    game.objects[PLAYER].speed = 0;
+    game.objects[PLAYER].energy = 0;
+    game.end_turn();
+    assert!(game.objects[PLAYER].ready());
+  }
+
+  #[test]
+  fn wading_slows_the_player_down() {
+    let mut game = Game::new(0, MapGenerator::Rooms, GameConfig::default());
+    game.objects.truncate(PLAYER + 1);
+    let (x, y) = game.objects[PLAYER].pos();
+    game.set_terrain(x + 1, y, Terrain::ShallowWater);
+    game.set_terrain(x + 2, y, Terrain::Floor);
+
+    game.move_by(PLAYER, 1, 0);
+    assert!(game.objects[PLAYER].has_effect(EffectKind::Slow));
+    assert_eq!(game.objects[PLAYER].speed_modifier, 100 - SOAKED_SLOW);
+
+    // and it dries off after a while
+    game.move_by(PLAYER, 1, 0);
+    for _ in 0..SOAKED_TURNS {
+      game.objects[PLAYER].tick_effects(&mut game.messages);
+    }
+    assert_eq!(game.objects[PLAYER].speed_modifier, 100);
+  }
+}
@@ -0,0 +1,497 @@
+use std::fmt;
+
+use crate::map::{Map, Region, Tile};
+use crate::rect::Rect;
+use crate::terrain::Terrain;
+
+/*
+ * Plain-text level files, so that levels can be saved, edited by hand and
+ * loaded back as fixed levels. A level file looks like:
+ *
+ *   ; comments start with a semicolon
+ *   == map
+ *   ##########
+ *   #@..o....#
+ *   #....+...#
+ *   ##########
+ *   == rooms
+ *   0 0 9 3
+ *   == objects
+ *   3 1 !
+ *
+ * The `map` section draws the terrain, with objects drawn right onto it
+ * (they stand on floor). `rooms` lists the corners of every room as
+ * `x1 y1 x2 y2`, and `objects` lists extra objects as `x y marker`, for
+ * when a tile holds more than one object or an object isn't on floor.
+ *
+ * Nothing on the edge of the map can be walked on, so that nobody walks
+ * off of it. The player starts at `@`, or anywhere that can be walked on.
+ */
+
+/// characters used for terrain in level files
+const TERRAIN_LEGEND: &[(char, Terrain)] = &[
+  ('.', Terrain::Floor),
+  ('#', Terrain::Wall),
+  ('~', Terrain::ShallowWater),
+  ('≈', Terrain::DeepWater),
+  ('^', Terrain::Lava),
+  ('"', Terrain::Grass),
+  (':', Terrain::Rubble),
+  ('_', Terrain::Chasm),
+  ('+', Terrain::ClosedDoor),
+  ('\'', Terrain::OpenDoor),
+  ('=', Terrain::LockedDoor),
+];
+
+/// characters for objects: the player, stairs, and the vault legend
+pub const OBJECT_MARKERS: &[char] = &['@', '<', '>', 'o', 'T', 'M', '!', '?', 'k', '&'];
+
+/// What went wrong while reading a level file, and where (both start at 1)
+#[derive(Debug, PartialEq)]
+pub struct ParseError {
+  pub line: usize,
+  pub column: usize,
+  pub message: String,
+}
+
+impl ParseError {
+  fn new<T: Into<String>>(line: usize, column: usize, message: T) -> Self {
+    ParseError {
+      line,
+      column,
+      message: message.into(),
+    }
+  }
+}
+
+impl fmt::Display for ParseError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}:{}: {}", self.line, self.column, self.message)
+  }
+}
+
+fn terrain_for(c: char) -> Option<Terrain> {
+  TERRAIN_LEGEND
+    .iter()
+    .find(|&&(legend, _)| legend == c)
+    .map(|&(_, terrain)| terrain)
+}
+
+fn char_for(terrain: Terrain) -> char {
+  TERRAIN_LEGEND
+    .iter()
+    .find(|&&(_, legend)| legend == terrain)
+    .map_or('?', |&(c, _)| c)
+}
+
+/// a word of a line, along with the column it starts at
+type Field<'a> = (usize, &'a str);
+
+/// split a line of a section into its whitespace-separated fields
+fn fields(line: &str) -> Vec<Field<'_>> {
+  let mut fields = vec![];
+  let mut start = None;
+  for (i, (byte, c)) in line.char_indices().enumerate() {
+    match (c.is_whitespace(), start) {
+      (false, None) => start = Some((i, byte)),
+      (true, Some((column, from))) => {
+        fields.push((column + 1, &line[from..byte]));
+        start = None;
+      }
+      _ => {}
+    }
+  }
+  if let Some((column, from)) = start {
+    fields.push((column + 1, &line[from..]));
+  }
+  fields
+}
+
+/// parse `count` numbers out of a line, e.g. the corners of a room
+fn numbers(
+  line_no: usize,
+  line: &str,
+  count: usize,
+) -> Result<(Vec<i32>, Vec<Field<'_>>), ParseError> {
+  let fields = fields(line);
+  if fields.len() < count {
+    return Err(ParseError::new(
+      line_no,
+      line.chars().count() + 1,
+      format!("expected {} numbers", count),
+    ));
+  }
+
+  let mut values = vec![];
+  for &(column, field) in &fields[..count] {
+    let value = field
+      .parse()
+      .map_err(|_| ParseError::new(line_no, column, format!("`{}` is not a number", field)))?;
+    values.push(value);
+  }
+  Ok((values, fields[count..].to_vec()))
+}
+
+/// read a level file into a map, which can't be bigger than `max_width` by `max_height`
+pub fn parse(text: &str, max_width: i32, max_height: i32) -> Result<Map, ParseError> {
+  let mut rows: Vec<(usize, Vec<char>)> = vec![];
+  let mut rooms = vec![];
+  let mut spawns = vec![];
+  let mut section = None;
+
+  for (i, line) in text.lines().enumerate() {
+    let line_no = i + 1;
+    let line = line.trim_end_matches('\r');
+    if line.trim().is_empty() || line.starts_with(';') {
+      continue;
+    }
+
+    if let Some(name) = line.strip_prefix("== ") {
+      section = match name.trim() {
+        "map" | "rooms" | "objects" => Some(name.trim()),
+        other => {
+          return Err(ParseError::new(
+            line_no,
+            4,
+            format!("unknown section `{}`", other),
+          ))
+        }
+      };
+      continue;
+    }
+
+    match section {
+      Some("map") => {
+        let row: Vec<char> = line.chars().collect();
+        if row.len() as i32 > max_width {
+          return Err(ParseError::new(
+            line_no,
+            max_width as usize + 1,
+            format!("the map can't be wider than {} tiles", max_width),
+          ));
+        }
+        if rows.len() as i32 >= max_height {
+          return Err(ParseError::new(
+            line_no,
+            1,
+            format!("the map can't be taller than {} tiles", max_height),
+          ));
+        }
+        if let Some((_, first)) = rows.first() {
+          if row.len() != first.len() {
+            return Err(ParseError::new(
+              line_no,
+              row.len().min(first.len()) + 1,
+              format!("row is {} tiles wide, expected {}", row.len(), first.len()),
+            ));
+          }
+        }
+        rows.push((line_no, row));
+      }
+      Some("rooms") => {
+        let (corners, rest) = numbers(line_no, line, 4)?;
+        if let Some(&(column, _)) = rest.first() {
+          return Err(ParseError::new(
+            line_no,
+            column,
+            "unexpected text after room",
+          ));
+        }
+        rooms.push((
+          line_no,
+          Rect {
+            x1: corners[0],
+            y1: corners[1],
+            x2: corners[2],
+            y2: corners[3],
+          },
+        ));
+      }
+      Some("objects") => {
+        let (pos, rest) = numbers(line_no, line, 2)?;
+        let marker = match rest.as_slice() {
+          [(column, marker)] => {
+            let mut chars = marker.chars();
+            match (chars.next(), chars.next()) {
+              (Some(c), None) if OBJECT_MARKERS.contains(&c) => c,
+              _ => {
+                return Err(ParseError::new(
+                  line_no,
+                  *column,
+                  format!("unknown object `{}`", marker),
+                ))
+              }
+            }
+          }
+          _ => {
+            return Err(ParseError::new(
+              line_no,
+              line.chars().count() + 1,
+              "expected `x y marker`",
+            ))
+          }
+        };
+        spawns.push((line_no, (pos[0], pos[1], marker)));
+      }
+      _ => {
+        return Err(ParseError::new(
+          line_no,
+          1,
+          "expected a section header like `== map`",
+        ))
+      }
+    }
+  }
+
+  if rows.is_empty() {
+    return Err(ParseError::new(
+      text.lines().count().max(1),
+      1,
+      "the level has no `== map` section",
+    ));
+  }
+
+  let (width, height) = (rows[0].1.len() as i32, rows.len() as i32);
+  let mut map = Map::solid(width, height);
+
+  for (y, (line_no, row)) in rows.iter().enumerate() {
+    for (x, &c) in row.iter().enumerate() {
+      let (x, y) = (x as i32, y as i32);
+      if let Some(terrain) = terrain_for(c) {
+        map.set_tile(x, y, Tile::new(terrain));
+      } else if OBJECT_MARKERS.contains(&c) {
+        map.set_tile(x, y, Tile::empty());
+        map.spawns.push((x, y, c));
+      } else {
+        return Err(ParseError::new(
+          *line_no,
+          x as usize + 1,
+          format!("unknown tile `{}`", c),
+        ));
+      }
+    }
+  }
+
+  // keep everyone on the map
+  for (y, (line_no, _)) in rows.iter().enumerate() {
+    let y = y as i32;
+    for x in 0..width {
+      let edge = x == 0 || y == 0 || x == width - 1 || y == height - 1;
+      if edge && map.tile_at(x, y).terrain.is_passable() {
+        return Err(ParseError::new(
+          *line_no,
+          x as usize + 1,
+          "the edge of the map must be walled off",
+        ));
+      }
+    }
+  }
+
+  let inside = |x: i32, y: i32| x >= 0 && y >= 0 && x < width && y < height;
+  for (line_no, room) in rooms {
+    if !inside(room.x1, room.y1)
+      || !inside(room.x2, room.y2)
+      || room.x1 > room.x2
+      || room.y1 > room.y2
+    {
+      return Err(ParseError::new(line_no, 1, "room is outside of the map"));
+    }
+    map.rooms.push(room);
+  }
+  for (line_no, (x, y, marker)) in spawns {
+    if !inside(x, y) {
+      return Err(ParseError::new(line_no, 1, "object is outside of the map"));
+    }
+    if marker == '@' && map.tile_at(x, y).blocked() {
+      return Err(ParseError::new(
+        line_no,
+        1,
+        "the player can't start in a wall",
+      ));
+    }
+    map.spawns.push((x, y, marker));
+  }
+
+  // spawn regions are the walkable parts of the rooms, or the whole map
+  // if there are no rooms
+  let walkable = |&(x, y): &(i32, i32)| !map.tile_at(x, y).blocked();
+  let mut regions: Vec<Region> = map
+    .rooms
+    .iter()
+    .map(|room| room.iter_points().filter(walkable).collect::<Vec<_>>())
+    .filter(|points| !points.is_empty())
+    .map(Region::from_points)
+    .collect();
+  if regions.is_empty() {
+    let points: Vec<_> = (0..width)
+      .flat_map(|x| (0..height).map(move |y| (x, y)))
+      .filter(walkable)
+      .collect();
+    if !points.is_empty() {
+      regions.push(Region::from_points(points));
+    }
+  }
+  map.regions = regions;
+
+  let has_start = map.spawns.iter().any(|&(_, _, marker)| marker == '@');
+  if !has_start && map.regions.is_empty() {
+    return Err(ParseError::new(
+      text.lines().count(),
+      1,
+      "the level has nowhere for the player to start",
+    ));
+  }
+
+  Ok(map)
+}
+
+/// write a map to a level file, along with objects given by their marker
+pub fn write(map: &Map, spawns: &[(i32, i32, char)]) -> String {
+  let mut grid: Vec<Vec<char>> = (0..map.height)
+    .map(|y| {
+      (0..map.width)
+        .map(|x| char_for(map.tile_at(x, y).terrain))
+        .collect()
+    })
+    .collect();
+
+  // objects go right onto the floor, when they have it to themselves
+  let mut extra = vec![];
+  for &(x, y, marker) in spawns {
+    let cell = &mut grid[y as usize][x as usize];
+    if *cell == char_for(Terrain::Floor) {
+      *cell = marker;
+    } else {
+      extra.push((x, y, marker));
+    }
+  }
+
+  let mut text = String::from("== map\n");
+  for row in grid {
+    text.extend(row);
+    text.push('\n');
+  }
+
+  if !map.rooms.is_empty() {
+    text.push_str("== rooms\n");
+    for room in &map.rooms {
+      text.push_str(&format!(
+        "{} {} {} {}\n",
+        room.x1, room.y1, room.x2, room.y2
+      ));
+    }
+  }
+
+  if !extra.is_empty() {
+    text.push_str("== objects\n");
+    for (x, y, marker) in extra {
+      text.push_str(&format!("{} {} {}\n", x, y, marker));
+    }
+  }
+
+  text
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{parse, write, ParseError};
+
+  const LEVEL: &str = "\
+== map
+##########
+#@..o....#
+#....+..>#
+##########
+== rooms
+0 0 9 3
+== objects
+5 2 k
+";
+
+  /// the error a level file fails with
+  fn error(text: &str) -> ParseError {
+    parse(text, 80, 43)
+      .err()
+      .expect("the level should not parse")
+  }
+
+  #[test]
+  fn round_trip() {
+    let map = parse(LEVEL, 80, 43).unwrap();
+    assert_eq!((map.width, map.height), (10, 4));
+    assert_eq!(map.rooms.len(), 1);
+    assert_eq!(map.regions.len(), 1);
+
+    let text = write(&map, &map.spawns);
+    assert_eq!(text, LEVEL);
+
+    let again = parse(&text, 80, 43).unwrap();
+    assert_eq!(again.spawns, map.spawns);
+    for y in 0..map.height {
+      for x in 0..map.width {
+        assert_eq!(again.tile_at(x, y).terrain, map.tile_at(x, y).terrain);
+      }
+    }
+  }
+
+  #[test]
+  fn comments_and_blank_lines_are_skipped() {
+    let text = format!(
+      "; a level\n\n{}",
+      LEVEL.replace("== rooms", "; rooms\n== rooms")
+    );
+    assert!(parse(&text, 80, 43).is_ok());
+  }
+
+  #[test]
+  fn open_edge() {
+    let err = error("== map\n#####\n#@...\n#####\n");
+    assert_eq!((err.line, err.column), (3, 5));
+
+    let err = error("== map\n##.##\n#@..#\n#####\n");
+    assert_eq!((err.line, err.column), (2, 3));
+
+    // objects drawn on the edge stand on floor, too
+    let err = error("== map\n#####\n#@..#\n##o##\n");
+    assert_eq!((err.line, err.column), (4, 3));
+  }
+
+  #[test]
+  fn no_start() {
+    let err = error("== map\n#####\n#####\n#####\n");
+    assert_eq!(err.message, "the level has nowhere for the player to start");
+
+    // without `@`, the player starts anywhere that can be walked on
+    assert!(parse("== map\n####\n#..#\n####\n", 80, 43).is_ok());
+
+    let err = error("== map\n####\n#..#\n####\n== objects\n0 0 @\n");
+    assert_eq!(err.line, 6);
+  }
+
+  #[test]
+  fn malformed() {
+    assert_eq!(error("== map\n###\n##\n").line, 3);
+    assert_eq!(error("== map\n###\n#Q#\n###\n").column, 2);
+    assert_eq!(error("== stuff\n").message, "unknown section `stuff`");
+    assert_eq!(error("###\n").line, 1);
+    assert_eq!(
+      error("; nothing\n").message,
+      "the level has no `== map` section"
+    );
+    assert_eq!(
+      error(&format!(
+        "{}9 9 9 9\n",
+        LEVEL.replace("== objects\n5 2 k\n", "")
+      ))
+      .line,
+      8
+    );
+    assert_eq!(error(&format!("{}1 x k\n", LEVEL)).column, 3);
+    assert_eq!(
+      error(&format!("{}1 1 Q\n", LEVEL)).message,
+      "unknown object `Q`"
+    );
+    assert_eq!(parse(LEVEL, 5, 43).err().map(|err| err.line), Some(2));
+    assert_eq!(parse(LEVEL, 80, 3).err().map(|err| err.line), Some(5));
+  }
+}
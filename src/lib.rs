@@ -1,21 +1,36 @@
-use wasm_bindgen::prelude::wasm_bindgen;
+use wasm_bindgen::prelude::{wasm_bindgen, JsValue};
 use web_sys::CanvasRenderingContext2d;
 
 pub mod ai;
+pub mod bsp;
+pub mod cave;
 pub mod colors;
 pub mod config;
+pub mod connectivity;
+pub mod dice;
+pub mod dijkstra;
 pub mod draw;
+pub mod effects;
 pub mod fov;
 pub mod game;
+pub mod level_file;
+pub mod light;
+pub mod los;
 pub mod map;
 pub mod mem;
 pub mod object;
+pub mod path;
 pub mod rect;
+pub mod shadowcasting;
+pub mod terrain;
 pub mod ui;
+pub mod vault;
+pub mod weapon;
 
-use config::*;
+use config::{GameConfig, PLAYER};
 use draw::Tcod;
 use game::Game;
+use map::MapGenerator;
 
 // A macro to provide `println!(..)`-style syntax for `console.log` logging.
 // macro_rules! log {
@@ -34,20 +49,41 @@ pub struct GameData {
 
 #[wasm_bindgen]
 impl GameData {
+  /// `config` is an optional JSON string with `GameConfig` fields, e.g.
+  /// `{ "torch_radius": 6 }`. Fields that are left out keep their defaults
   #[wasm_bindgen(constructor)]
-  pub fn new(seed: i32) -> Self {
-    Self {
-      tcod: Tcod::new(SCREEN_WIDTH, SCREEN_HEIGHT),
-      game: Game::new(seed as u64),
+  pub fn new(
+    seed: i32,
+    generator: MapGenerator,
+    config: Option<String>,
+  ) -> Result<GameData, JsValue> {
+    let config = match config {
+      Some(json) => GameConfig::from_json(&json).map_err(|e| JsValue::from_str(&e))?,
+      None => GameConfig::default(),
+    };
+
+    Ok(Self {
+      tcod: Tcod::new(config.screen_width, config.screen_height),
+      game: Game::new(seed as u64, generator, config),
       key: 0,
       mouse: (0, 0),
-    }
+    })
+  }
+
+  /// width of the screen, in characters
+  pub fn width(&self) -> i32 {
+    self.tcod.w
+  }
+
+  /// height of the screen, in characters
+  pub fn height(&self) -> i32 {
+    self.tcod.h
   }
 
   pub fn move_mouse(&mut self, x: f32, y: f32) {
     self.mouse = (
-      (x * SCREEN_WIDTH as f32) as i32,
-      (y * SCREEN_HEIGHT as f32) as i32,
+      (x * self.tcod.w as f32) as i32,
+      (y * self.tcod.h as f32) as i32,
     );
   }
 
@@ -57,12 +93,28 @@ impl GameData {
   }
 
   pub fn tick(&mut self) {
-    self.game.handle_keys(self.key);
-    self.key = 0;
+    // hold on to the key until the player has the energy to act on it
+    if self.game.objects[PLAYER].ready() {
+      self.game.handle_keys(self.key);
+      self.key = 0;
+    }
     self.game.update();
     self.game.render(&mut self.tcod, self.mouse);
   }
 
+  /// replace the current level with one from a level file (see `level_file.rs`)
+  pub fn load_level(&mut self, text: &str) -> Result<(), JsValue> {
+    self
+      .game
+      .load_level(text)
+      .map_err(|e| JsValue::from_str(&e.to_string()))
+  }
+
+  /// the current level as a level file
+  pub fn save_level(&self) -> String {
+    self.game.save_level()
+  }
+
   pub fn render_to_string(&self) -> String {
     self.tcod.render_to_string()
   }
@@ -0,0 +1,343 @@
+use std::mem;
+
+use crate::colors::Color;
+use crate::fov::FOV;
+use crate::rect::Rect;
+
+/*
+ * Light sources, like the player's torch, braziers or glowing lava, add up
+ * into a light map with a color for every tile. A tile can only be seen when
+ * it is both in the field of view and lit, and its color goes from dark to
+ * lit with the amount of light on it.
+ */
+
+/// below this much light, a tile is too dark to make anything out
+const MIN_BRIGHTNESS: f32 = 0.05;
+
+/// How quickly light fades with distance
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Falloff {
+  Constant,
+  Linear,
+  Quadratic,
+}
+
+/// A light source, attached to an object or a tile
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Light {
+  pub radius: i32,
+  pub color: Color,
+  pub falloff: Falloff,
+}
+
+impl Light {
+  pub fn new(radius: i32, color: Color, falloff: Falloff) -> Self {
+    Light {
+      radius,
+      color,
+      falloff,
+    }
+  }
+
+  /// how strong the light is (between 0 and 1) at an offset from its source
+  pub fn intensity(&self, dx: i32, dy: i32) -> f32 {
+    let distance = ((dx * dx + dy * dy) as f32).sqrt();
+    if distance > self.radius as f32 {
+      return 0.0;
+    }
+
+    // the edge of the radius still gets a little light
+    let t = 1.0 - distance / (self.radius + 1) as f32;
+    match self.falloff {
+      Falloff::Constant => 1.0,
+      Falloff::Linear => t,
+      Falloff::Quadratic => t * t,
+    }
+  }
+}
+
+/// The amount of red, green and blue light on a tile, where 1 is full light
+type Rgb = (f32, f32, f32);
+
+/// A light source that has been cast, with the light it puts on each tile of
+/// its `area`
+struct LitSource {
+  x: i32,
+  y: i32,
+  light: Light,
+  /// the square around the source that its radius reaches, corners included
+  area: Rect,
+  cells: Vec<Rgb>,
+}
+
+impl LitSource {
+  fn new(fov: &mut FOV, x: i32, y: i32, light: Light, width: i32, height: i32) -> Self {
+    fov.compute_fov(x, y, light.radius, true);
+
+    let r = light.radius;
+    let area = Rect {
+      x1: (x - r).max(0),
+      y1: (y - r).max(0),
+      x2: (x + r).min(width - 1),
+      y2: (y + r).min(height - 1),
+    };
+    let mut cells =
+      Vec::with_capacity(((area.x2 - area.x1 + 1) * (area.y2 - area.y1 + 1)) as usize);
+    for ty in area.y1..=area.y2 {
+      for tx in area.x1..=area.x2 {
+        let intensity = light.intensity(tx - x, ty - y);
+        if intensity > 0.0 && fov.is_in_fov(tx, ty) {
+          cells.push(to_rgb(light.color, intensity));
+        } else {
+          cells.push((0.0, 0.0, 0.0));
+        }
+      }
+    }
+
+    LitSource {
+      x,
+      y,
+      light,
+      area,
+      cells,
+    }
+  }
+
+  fn reaches(&self, x: i32, y: i32) -> bool {
+    x >= self.area.x1 && x <= self.area.x2 && y >= self.area.y1 && y <= self.area.y2
+  }
+
+  fn light_at(&self, x: i32, y: i32) -> Rgb {
+    let width = self.area.x2 - self.area.x1 + 1;
+    self.cells[((x - self.area.x1) + (y - self.area.y1) * width) as usize]
+  }
+}
+
+/// the smallest rect covering both `a` and `b`
+fn union(a: Option<Rect>, b: Rect) -> Rect {
+  match a {
+    Some(a) => Rect {
+      x1: a.x1.min(b.x1),
+      y1: a.y1.min(b.y1),
+      x2: a.x2.max(b.x2),
+      y2: a.y2.max(b.y2),
+    },
+    None => b,
+  }
+}
+
+/// The light on every tile of a map
+pub struct LightMap {
+  width: i32,
+  height: i32,
+  cells: Vec<Rgb>,
+  /// the ambient light the cells were lit with, `None` before the first `compute`
+  ambient: Option<Rgb>,
+  /// the sources that lit up the cells, kept so unchanged ones aren't cast again
+  sources: Vec<LitSource>,
+  /// the cells whose light changed during the last `compute`
+  changed: Vec<(i32, i32)>,
+}
+
+impl LightMap {
+  pub fn new(width: i32, height: i32) -> Self {
+    LightMap {
+      width,
+      height,
+      cells: vec![(0.0, 0.0, 0.0); (width * height) as usize],
+      ambient: None,
+      sources: vec![],
+      changed: vec![],
+    }
+  }
+
+  /// light up the map with `sources` on top of the `ambient` light. Walls
+  /// block light the same way they block sight, using `fov`. Only the sources
+  /// that are new, moved, or reach one of the `changed_tiles` are cast again,
+  /// and only the cells around them are updated
+  pub fn compute(
+    &mut self,
+    fov: &mut FOV,
+    sources: &[(i32, i32, Light)],
+    ambient: Color,
+    changed_tiles: &[(i32, i32)],
+  ) {
+    self.changed.clear();
+
+    // the first time, or when the ambient light changes, every cell is affected
+    let ambient = to_rgb(ambient, 1.0);
+    let mut dirty = None;
+    if self.ambient != Some(ambient) {
+      self.ambient = Some(ambient);
+      dirty = Some(Rect {
+        x1: 0,
+        y1: 0,
+        x2: self.width - 1,
+        y2: self.height - 1,
+      });
+    }
+
+    let mut previous = mem::take(&mut self.sources);
+    for &(x, y, light) in sources {
+      let unchanged = previous.iter().position(|source| {
+        source.x == x
+          && source.y == y
+          && source.light == light
+          && !changed_tiles.iter().any(|&(tx, ty)| source.reaches(tx, ty))
+      });
+      let source = match unchanged {
+        Some(i) => previous.swap_remove(i),
+        None => {
+          let source = LitSource::new(fov, x, y, light, self.width, self.height);
+          dirty = Some(union(dirty, source.area));
+          source
+        }
+      };
+      self.sources.push(source);
+    }
+
+    // the lights that went out or moved leave their old area to be lit again
+    for source in &previous {
+      dirty = Some(union(dirty, source.area));
+    }
+
+    let dirty = match dirty {
+      Some(dirty) => dirty,
+      None => return,
+    };
+
+    let width = dirty.x2 - dirty.x1 + 1;
+    let mut cells = vec![ambient; (width * (dirty.y2 - dirty.y1 + 1)) as usize];
+    for source in self
+      .sources
+      .iter()
+      .filter(|source| source.area.intersects_with(&dirty))
+    {
+      for ty in source.area.y1.max(dirty.y1)..=source.area.y2.min(dirty.y2) {
+        for tx in source.area.x1.max(dirty.x1)..=source.area.x2.min(dirty.x2) {
+          let (lr, lg, lb) = source.light_at(tx, ty);
+          let cell = &mut cells[((tx - dirty.x1) + (ty - dirty.y1) * width) as usize];
+          *cell = (cell.0 + lr, cell.1 + lg, cell.2 + lb);
+        }
+      }
+    }
+
+    for ty in dirty.y1..=dirty.y2 {
+      for tx in dirty.x1..=dirty.x2 {
+        let cell = cells[((tx - dirty.x1) + (ty - dirty.y1) * width) as usize];
+        let i = (tx + ty * self.width) as usize;
+        if cell != self.cells[i] {
+          self.cells[i] = cell;
+          self.changed.push((tx, ty));
+        }
+      }
+    }
+  }
+
+  /// the cells whose light changed during the last `compute`
+  pub fn changed(&self) -> &[(i32, i32)] {
+    &self.changed
+  }
+
+  /// the strongest color of light on a tile, which can be more than 1
+  pub fn brightness(&self, x: i32, y: i32) -> f32 {
+    let (r, g, b) = self.cells[(x + y * self.width) as usize];
+    r.max(g).max(b)
+  }
+
+  /// is there enough light to see the tile?
+  pub fn is_lit(&self, x: i32, y: i32) -> bool {
+    self.brightness(x, y) >= MIN_BRIGHTNESS
+  }
+
+  /// blend the `dark` and `lit` colors of a tile, according to the light on it
+  pub fn shade(&self, x: i32, y: i32, dark: Color, lit: Color) -> Color {
+    let (r, g, b) = self.cells[(x + y * self.width) as usize];
+    let blend = |dark: u8, lit: u8, light: f32| {
+      let light = light.min(1.0);
+      (dark as f32 + (lit as f32 - dark as f32) * light).round() as u8
+    };
+    Color::new(
+      blend(dark.r, lit.r, r),
+      blend(dark.g, lit.g, g),
+      blend(dark.b, lit.b, b),
+    )
+  }
+}
+
+fn to_rgb(color: Color, intensity: f32) -> Rgb {
+  (
+    color.r as f32 / 255.0 * intensity,
+    color.g as f32 / 255.0 * intensity,
+    color.b as f32 / 255.0 * intensity,
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::colors;
+  use crate::fov::FovKind;
+
+  /// an open 20x20 room with a wall across part of it
+  fn room() -> FOV {
+    let mut fov = FOV::new(20, 20, FovKind::Shadowcasting);
+    for y in 0..20 {
+      for x in 0..20 {
+        let wall = x == 0 || y == 0 || x == 19 || y == 19 || (x == 10 && y < 12);
+        fov.set(x, y, !wall, !wall);
+      }
+    }
+    fov
+  }
+
+  /// light a map from scratch, to compare against the incremental result
+  fn fresh(fov: &mut FOV, sources: &[(i32, i32, Light)]) -> LightMap {
+    let mut lights = LightMap::new(20, 20);
+    lights.compute(fov, sources, colors::BLACK, &[]);
+    lights
+  }
+
+  fn assert_same(a: &LightMap, b: &LightMap) {
+    for y in 0..20 {
+      for x in 0..20 {
+        assert_eq!(a.brightness(x, y), b.brightness(x, y), "at {} {}", x, y);
+      }
+    }
+  }
+
+  #[test]
+  fn incremental_matches_fresh() {
+    let torch = Light::new(5, colors::WHITE, Falloff::Linear);
+    let lava = Light::new(3, colors::FLAME, Falloff::Quadratic);
+    let mut fov = room();
+    let mut lights = LightMap::new(20, 20);
+
+    let mut sources = vec![(5, 5, torch), (14, 4, lava)];
+    lights.compute(&mut fov, &sources, colors::BLACK, &[]);
+    assert_same(&lights, &fresh(&mut fov, &sources));
+
+    // nothing changed, so nothing is lit again
+    lights.compute(&mut fov, &sources, colors::BLACK, &[]);
+    assert!(lights.changed().is_empty());
+
+    // the torch moves
+    sources[0] = (6, 5, torch);
+    lights.compute(&mut fov, &sources, colors::BLACK, &[]);
+    assert!(!lights.changed().is_empty());
+    assert!(lights.changed().iter().all(|&(x, _)| x <= 11));
+    assert_same(&lights, &fresh(&mut fov, &sources));
+
+    // a hole opens in the wall next to the torch
+    fov.set(10, 5, true, true);
+    lights.compute(&mut fov, &sources, colors::BLACK, &[(10, 5)]);
+    assert!(lights.brightness(12, 5) > 0.0);
+    assert_same(&lights, &fresh(&mut fov, &sources));
+
+    // the lava goes out
+    sources.pop();
+    lights.compute(&mut fov, &sources, colors::BLACK, &[]);
+    assert_eq!(lights.brightness(14, 4), 0.0);
+    assert_same(&lights, &fresh(&mut fov, &sources));
+  }
+}
@@ -0,0 +1,127 @@
+use bresenham::Bresenham;
+
+use crate::game::Game;
+use crate::map::Map;
+
+/*
+ * Line of sight and line of fire between two points, for ranged attacks,
+ * spells and AI. Lines are traced with Bresenham's algorithm, like the rays
+ * of `fov.rs`. The points at either end never block the line: a monster
+ * can see a wall, and an arrow can hit whatever it's aimed at.
+ */
+
+/// What stopped a line
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Obstacle {
+  /// a tile that can't be seen through
+  Tile(i32, i32),
+  /// a blocking object, by its index in `Game::objects`
+  Object(usize),
+}
+
+/// The result of tracing a line from one point to another
+#[derive(Clone, Debug, PartialEq)]
+pub struct Trace {
+  /// the cells the line went through, without the start. Ends at the
+  /// obstacle if there is one, or at the target
+  pub path: Vec<(i32, i32)>,
+  pub obstacle: Option<Obstacle>,
+  /// did the line reach the target?
+  pub clear: bool,
+}
+
+/// every cell on the line from `from` to `to`, both included
+pub fn line(from: (i32, i32), to: (i32, i32)) -> impl Iterator<Item = (i32, i32)> {
+  Bresenham::new(
+    (from.0 as isize, from.1 as isize),
+    (to.0 as isize, to.1 as isize),
+  )
+  .map(|(x, y)| (x as i32, y as i32))
+  .chain(std::iter::once(to))
+}
+
+/// follow the line until `obstacle_at` finds something in the way
+fn trace<F>(from: (i32, i32), to: (i32, i32), obstacle_at: F) -> Trace
+where
+  F: Fn(i32, i32) -> Option<Obstacle>,
+{
+  let mut path = vec![];
+  for (x, y) in line(from, to).skip(1) {
+    path.push((x, y));
+    if (x, y) == to {
+      break;
+    }
+    if let Some(obstacle) = obstacle_at(x, y) {
+      return Trace {
+        path,
+        obstacle: Some(obstacle),
+        clear: false,
+      };
+    }
+  }
+
+  Trace {
+    path,
+    obstacle: None,
+    clear: true,
+  }
+}
+
+/// trace the view from `from` to `to`, which is blocked by opaque tiles
+pub fn line_of_sight(map: &Map, from: (i32, i32), to: (i32, i32)) -> Trace {
+  trace(from, to, |x, y| {
+    if map.tile_at(x, y).block_sight() {
+      Some(Obstacle::Tile(x, y))
+    } else {
+      None
+    }
+  })
+}
+
+/// can `from` and `to` see each other? Bresenham's line from one end isn't
+/// always the same as the line from the other, so either being clear is
+/// enough, and the answer is the same both ways
+pub fn can_see(map: &Map, from: (i32, i32), to: (i32, i32)) -> bool {
+  line_of_sight(map, from, to).clear || line_of_sight(map, to, from).clear
+}
+
+/// trace a shot from `from` to `to`, which is stopped by opaque tiles and
+/// blocking objects. It flies over water, lava and chasms
+pub fn line_of_fire(game: &Game, from: (i32, i32), to: (i32, i32)) -> Trace {
+  trace(from, to, |x, y| {
+    if game.map.tile_at(x, y).block_sight() {
+      Some(Obstacle::Tile(x, y))
+    } else {
+      game.blocking_object_at(x, y).map(Obstacle::Object)
+    }
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use pcg_rand::{seeds::PcgSeeder, Pcg32Basic};
+  use rand::SeedableRng;
+
+  use super::*;
+  use crate::config::GameConfig;
+  use crate::map::MapGenerator;
+
+  #[test]
+  fn can_see_is_symmetric() {
+    let mut rng = Pcg32Basic::from_seed(PcgSeeder::seed(3));
+    let map = Map::new(&mut rng, MapGenerator::Cave, &GameConfig::default());
+    let open: Vec<_> = (0..map.width)
+      .flat_map(|x| (0..map.height).map(move |y| (x, y)))
+      .filter(|&(x, y)| !map.tile_at(x, y).block_sight())
+      .collect();
+
+    for &a in &open {
+      for &b in &open {
+        let (dx, dy) = (a.0 - b.0, a.1 - b.1);
+        if dx * dx + dy * dy <= 64 {
+          assert_eq!(can_see(&map, a, b), can_see(&map, b, a), "{:?} {:?}", a, b);
+        }
+      }
+    }
+  }
+}
@@ -1,60 +1,160 @@
 use rand::Rng;
 use std::cmp;
+use wasm_bindgen::prelude::wasm_bindgen;
 
-use crate::config::*;
+use crate::bsp;
+use crate::cave;
+use crate::config::GameConfig;
+use crate::connectivity;
 use crate::rect::Rect;
+use crate::terrain::Terrain;
+use crate::vault;
 
 // A tile of the map and its properties
 #[derive(Clone, Copy, Debug)]
 pub struct Tile {
-  pub blocked: bool,
+  pub terrain: Terrain,
   pub explored: bool,
-  pub block_sight: bool,
 }
 
 impl Tile {
-  pub fn empty() -> Self {
+  pub fn new(terrain: Terrain) -> Self {
     Tile {
-      blocked: false,
+      terrain,
       explored: false,
-      block_sight: false,
     }
   }
 
+  pub fn empty() -> Self {
+    Tile::new(Terrain::Floor)
+  }
+
   pub fn wall() -> Self {
-    Tile {
-      blocked: true,
-      explored: false,
-      block_sight: true,
+    Tile::new(Terrain::Wall)
+  }
+
+  pub fn blocked(&self) -> bool {
+    !self.terrain.is_walkable()
+  }
+
+  pub fn block_sight(&self) -> bool {
+    !self.terrain.is_transparent()
+  }
+}
+
+/// Algorithm used to lay out a new map, chosen when the game is created
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MapGenerator {
+  /// throw random rooms at the map and discard the ones that overlap
+  Rooms,
+  /// recursively split the map and put a room in every partition
+  Bsp,
+  /// organic caves grown with a cellular automaton
+  Cave,
+}
+
+/// A group of floor tiles that monsters and the player can be spawned on,
+/// e.g. the inside of a room or a section of a cave
+#[derive(Clone, Debug)]
+pub struct Region {
+  pub center: (i32, i32),
+  pub points: Vec<(i32, i32)>,
+  /// the inside of a vault, which comes with its own monsters and items
+  pub vault: bool,
+}
+
+impl Region {
+  pub fn from_room(room: &Rect) -> Self {
+    Region {
+      center: room.center(),
+      points: room.iter_points().collect(),
+      vault: false,
+    }
+  }
+
+  /// a region of arbitrary shape. Its center is the point closest to the middle
+  pub fn from_points(points: Vec<(i32, i32)>) -> Self {
+    let n = points.len() as i32;
+    let mid_x = points.iter().map(|p| p.0).sum::<i32>() / n;
+    let mid_y = points.iter().map(|p| p.1).sum::<i32>() / n;
+    let center = *points
+      .iter()
+      .min_by_key(|(x, y)| (x - mid_x).pow(2) + (y - mid_y).pow(2))
+      .unwrap();
+
+    Region {
+      center,
+      points,
+      vault: false,
     }
   }
 }
 
 pub struct Map {
+  pub width: i32,
+  pub height: i32,
   tiles: Vec<Vec<Tile>>,
   pub rooms: Vec<Rect>,
+  pub regions: Vec<Region>,
+  /// objects that must be created at a specific place, by legend character
+  pub spawns: Vec<(i32, i32, char)>,
 }
 
 impl Map {
-  pub fn new<R: Rng>(rng: &mut R) -> Self {
-    // fill map with "blocked" tiles
-    let mut map = Map {
-      tiles: vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize],
+  /// a map filled with "blocked" tiles
+  pub fn solid(width: i32, height: i32) -> Self {
+    Map {
+      width,
+      height,
+      tiles: vec![vec![Tile::wall(); height as usize]; width as usize],
       rooms: vec![],
-    };
+      regions: vec![],
+      spawns: vec![],
+    }
+  }
+
+  pub fn new<R: Rng>(rng: &mut R, generator: MapGenerator, config: &GameConfig) -> Self {
+    let mut map = Map::solid(config.map_width, config.map_height);
+
+    match generator {
+      MapGenerator::Rooms => map.place_random_rooms(rng, config),
+      MapGenerator::Bsp => bsp::generate(&mut map, rng, config),
+      MapGenerator::Cave => cave::generate(&mut map, rng, config),
+    }
 
-    for _ in 0..MAX_ROOMS {
+    if generator != MapGenerator::Cave {
+      map.place_doors(rng, config.door_chance);
+      map.scatter_rubble(rng, config.rubble_chance);
+    }
+
+    // room-based generators spawn things inside their rooms
+    if map.regions.is_empty() {
+      map.regions = map.rooms.iter().map(Region::from_room).collect();
+    }
+
+    // stamp some hand-made vaults into the remaining space
+    vault::place_vaults(&mut map, rng, config);
+
+    // make sure everything can be reached
+    connectivity::repair(&mut map, rng);
+
+    map
+  }
+
+  fn place_random_rooms<R: Rng>(&mut self, rng: &mut R, config: &GameConfig) {
+    for _ in 0..config.max_rooms {
       // random width and height
-      let w = rng.gen_range(ROOM_MIN_SIZE, ROOM_MAX_SIZE + 1);
-      let h = rng.gen_range(ROOM_MIN_SIZE, ROOM_MAX_SIZE + 1);
+      let w = rng.gen_range(config.room_min_size, config.room_max_size + 1);
+      let h = rng.gen_range(config.room_min_size, config.room_max_size + 1);
       // random position without going out of the boundaries of the map
-      let x = rng.gen_range(0, MAP_WIDTH - w);
-      let y = rng.gen_range(0, MAP_HEIGHT - h);
+      let x = rng.gen_range(0, self.width - w);
+      let y = rng.gen_range(0, self.height - h);
 
       let new_room = Rect::new(x, y, w, h);
 
       // run through the other rooms and see if they intersect with this one
-      let failed = map
+      let failed = self
         .rooms
         .iter()
         .any(|other_room| new_room.intersects_with(other_room));
@@ -63,46 +163,119 @@ impl Map {
         // this means there are no intersections, so this room is valid
 
         // "paint" it to the map's tiles
-        map.create_room(new_room);
-
-        // center coordinates of the new room, will be useful later
-        let (new_x, new_y) = new_room.center();
+        self.create_room(new_room);
 
-        if !map.rooms.is_empty() {
+        if !self.rooms.is_empty() {
           // all rooms after the first:
-
           // connect it to the previous room with a tunnel
+          let prev_room = self.rooms[self.rooms.len() - 1];
+          self.connect_rooms(&prev_room, &new_room, rng);
+        }
 
-          // center coordinates of the previous room
-          let (prev_x, prev_y) = map.rooms[map.rooms.len() - 1].center();
+        // finally, append the new room to the list
+        self.rooms.push(new_room);
+      }
+    }
+  }
 
-          // toss a coin (random bool value -- either true or false)
-          if rng.gen::<bool>() {
-            // first move horizontally, then vertically
-            map.create_h_tunnel(prev_x, new_x, prev_y);
-            map.create_v_tunnel(prev_y, new_y, new_x);
+  /// put doors where tunnels enter the rooms
+  fn place_doors<R: Rng>(&mut self, rng: &mut R, chance: f32) {
+    for i in 0..self.rooms.len() {
+      let room = self.rooms[i];
+      for x in room.x1..=room.x2 {
+        for y in room.y1..=room.y2 {
+          // only look at the walls of the room, skipping its corners
+          let on_side = x == room.x1 || x == room.x2;
+          let on_top = y == room.y1 || y == room.y2;
+          if on_side == on_top || self.tile_at(x, y).terrain != Terrain::Floor {
+            continue;
+          }
+
+          // a tunnel came through here if it has walls on either side
+          let ((ax, ay), (bx, by)) = if on_side {
+            ((x, y - 1), (x, y + 1))
           } else {
-            // first move vertically, then horizontally
-            map.create_v_tunnel(prev_y, new_y, prev_x);
-            map.create_h_tunnel(prev_x, new_x, new_y);
+            ((x - 1, y), (x + 1, y))
+          };
+          if self.tile_at(ax, ay).terrain == Terrain::Wall
+            && self.tile_at(bx, by).terrain == Terrain::Wall
+            && rng.gen::<f32>() < chance
+          {
+            self.set_tile(x, y, Tile::new(Terrain::ClosedDoor));
           }
         }
+      }
+    }
+  }
 
-        // finally, append the new room to the list
-        map.rooms.push(new_room);
+  /// litter some of the rooms with rubble
+  fn scatter_rubble<R: Rng>(&mut self, rng: &mut R, chance: f32) {
+    for i in 0..self.rooms.len() {
+      let room = self.rooms[i];
+      if rng.gen::<f32>() < chance {
+        for _ in 0..rng.gen_range(1, 5) {
+          let x = rng.gen_range(room.x1 + 1, room.x2);
+          let y = rng.gen_range(room.y1 + 1, room.y2);
+          self.set_tile(x, y, Tile::new(Terrain::Rubble));
+        }
       }
     }
+  }
 
-    map
+  /// paint a roughly round blob of terrain over the floor around a point,
+  /// using `inner` for its middle and `edge` for its ragged border
+  pub fn paint_blob<R: Rng>(
+    &mut self,
+    rng: &mut R,
+    (x, y): (i32, i32),
+    radius: i32,
+    inner: Terrain,
+    edge: Terrain,
+  ) {
+    for tx in cmp::max(1, x - radius)..cmp::min(self.width - 1, x + radius + 1) {
+      for ty in cmp::max(1, y - radius)..cmp::min(self.height - 1, y + radius + 1) {
+        if self.tile_at(tx, ty).terrain != Terrain::Floor {
+          continue;
+        }
+
+        let distance = (tx - x).pow(2) + (ty - y).pow(2);
+        if distance <= (radius - 1).pow(2) {
+          self.set_tile(tx, ty, Tile::new(inner));
+        } else if distance <= radius.pow(2) && rng.gen::<f32>() < 0.7 {
+          self.set_tile(tx, ty, Tile::new(edge));
+        }
+      }
+    }
   }
 
-  fn create_room(&mut self, room: Rect) {
+  pub fn create_room(&mut self, room: Rect) {
     // go through the tiles in the rectangle and make them passable
-    // for (x, y) in room.iter_points() {
-    for x in (room.x1 + 1)..room.x2 {
-      for y in (room.y1 + 1)..room.y2 {
-        self.tiles[x as usize][y as usize] = Tile::empty();
-      }
+    for (x, y) in room.iter_points() {
+      self.tiles[x as usize][y as usize] = Tile::empty();
+    }
+  }
+
+  /// dig an L-shaped tunnel between the centers of two rooms
+  pub fn connect_rooms<R: Rng>(&mut self, from: &Rect, to: &Rect, rng: &mut R) {
+    self.connect_points(from.center(), to.center(), rng);
+  }
+
+  /// dig an L-shaped tunnel between two points
+  pub fn connect_points<R: Rng>(
+    &mut self,
+    (prev_x, prev_y): (i32, i32),
+    (new_x, new_y): (i32, i32),
+    rng: &mut R,
+  ) {
+    // toss a coin (random bool value -- either true or false)
+    if rng.gen::<bool>() {
+      // first move horizontally, then vertically
+      self.create_h_tunnel(prev_x, new_x, prev_y);
+      self.create_v_tunnel(prev_y, new_y, new_x);
+    } else {
+      // first move vertically, then horizontally
+      self.create_v_tunnel(prev_y, new_y, prev_x);
+      self.create_h_tunnel(prev_x, new_x, new_y);
     }
   }
 
@@ -124,6 +297,15 @@ impl Map {
     &self.tiles[x as usize][y as usize]
   }
 
+  pub fn set_tile(&mut self, x: i32, y: i32, tile: Tile) {
+    self.tiles[x as usize][y as usize] = tile;
+  }
+
+  /// change the terrain of a tile, keeping whether it was explored
+  pub fn set_terrain(&mut self, x: i32, y: i32, terrain: Terrain) {
+    self.tiles[x as usize][y as usize].terrain = terrain;
+  }
+
   pub fn set_explored(&mut self, x: i32, y: i32) {
     self.tiles[x as usize][y as usize].explored = true;
   }
@@ -1,7 +1,25 @@
+use rand::Rng;
+
 use crate::ai::Ai;
 use crate::colors::{self, Color};
+use crate::dice::Dice;
 use crate::draw::Tcod;
+use crate::effects::{Effect, EffectKind, Effects};
+use crate::light::Light;
+use crate::map::Map;
 use crate::ui::Messages;
+use crate::weapon::{self, Swing, Weapon};
+
+/// sides of the die rolled to hit: rolling the highest is a critical hit,
+/// and rolling a 1 always misses
+const HIT_DIE: i32 = 20;
+/// what the hit roll (plus accuracy) must reach, on top of the target's evasion
+const HIT_THRESHOLD: i32 = 10;
+
+/// the energy it takes to act
+pub const ACTION_COST: i32 = 100;
+/// the speed of an object that acts every few ticks
+pub const NORMAL_SPEED: i32 = 20;
 
 /// This is a generic object: the player, a monster, an item, the stairs...
 /// It's always represented by a character on screen.
@@ -14,9 +32,19 @@ pub struct Object {
   pub name: String,
   pub blocks: bool,
   pub alive: bool,
+  pub always_visible: bool,
+  pub light: Option<Light>,
+  /// energy gained every tick. Acting costs `ACTION_COST`
+  pub speed: i32,
+  /// percentage applied to `speed`: more than 100 when hasted, less when slowed
+  pub speed_modifier: i32,
+  pub energy: i32,
   pub fighter: Option<Fighter>,
-  pub ai: Option<Ai>, // TODO: how to use duck module here?
-  pub attacking: Option<(i32, i32)>,
+  pub ai: Option<Ai>,
+  pub weapon: Option<Weapon>,
+  pub attacking: Option<Swing>,
+  /// ticks left before the weapon can be swung again
+  pub cooldown: i32,
 }
 
 // combat-related properties and methods (monster, player, NPC).
@@ -24,8 +52,13 @@ pub struct Object {
 pub struct Fighter {
   pub max_hp: i32,
   pub hp: i32,
-  pub defense: i32,
-  pub power: i32,
+  pub defense: i32, // taken off the damage of every hit
+  pub accuracy: i32,
+  pub evasion: i32,
+  pub damage: Dice,
+  /// what a critical hit does to the target, on top of the damage
+  pub on_critical: Option<Effect>,
+  pub effects: Effects,
   pub mtype: i32,
 }
 
@@ -63,9 +96,16 @@ impl Object {
       name: name.into(),
       blocks: blocks,
       alive: false,
+      always_visible: false,
+      light: None,
+      speed: NORMAL_SPEED,
+      speed_modifier: 100,
+      energy: 0,
       ai: None,
       fighter: None,
+      weapon: None,
       attacking: None,
+      cooldown: 0,
     }
   }
 
@@ -76,28 +116,37 @@ impl Object {
   }
 
   /// set the color and then draw the character that represents this object at its position
-  pub fn draw(&self, tcod: &mut Tcod) {
+  pub fn draw(&self, tcod: &mut Tcod, map: &Map) {
     tcod.stroke(self.color);
     tcod.put_char(self.x, self.y, self.char);
 
-    if let Some((dx, dy)) = self.attacking {
-      tcod.stroke(colors::WHITE);
-      let char = match (dx, dy) {
-        (0, 1) => '|',
-        (0, -1) => '|',
-        (1, 0) => '-',
-        // (-1, 0) => '-',
-        _ => '-',
-      };
-      tcod.put_char(self.x + dx, self.y + dy, char);
+    // draw the weapon swing, in the shape of the weapon's pattern
+    tcod.stroke(colors::WHITE);
+    for (x, y) in self.swing_tiles(map) {
+      tcod.put_char(x, y, weapon::glyph((x - self.x, y - self.y)));
     }
   }
 
+  /// build up energy for a tick, faster when hasted and slower when slowed
+  pub fn gain_energy(&mut self) {
+    self.energy += self.speed * self.speed_modifier / 100;
+  }
+
+  /// does it have enough energy to act?
+  pub fn ready(&self) -> bool {
+    self.energy >= ACTION_COST
+  }
+
   pub fn set_pos(&mut self, x: i32, y: i32) {
     self.x = x;
     self.y = y;
   }
 
+  /// the character that represents this object on screen
+  pub fn glyph(&self) -> char {
+    self.char
+  }
+
   pub fn pos(&self) -> (i32, i32) {
     (self.x, self.y)
   }
@@ -123,6 +172,59 @@ impl Object {
     }
   }
 
+  pub fn has_effect(&self, kind: EffectKind) -> bool {
+    match self.fighter {
+      Some(fighter) => fighter.effects.has(kind),
+      None => false,
+    }
+  }
+
+  /// put a status effect on it, if it's a fighter
+  pub fn add_effect(&mut self, effect: Effect, messages: &mut Messages) {
+    let fighter = match self.fighter.as_mut() {
+      Some(fighter) => fighter,
+      None => return,
+    };
+
+    if fighter.effects.add(effect) {
+      messages.add(
+        format!("{} is {}!", self.name, effect.kind.name()),
+        effect.kind.color(),
+      );
+      if effect.kind == EffectKind::Confusion {
+        if let Some(ai) = self.ai.as_mut() {
+          ai.confuse();
+        }
+      }
+    }
+    self.speed_modifier = fighter.effects.speed_modifier();
+  }
+
+  /// let the status effects do their thing at the start of a turn, and
+  /// count them down
+  pub fn tick_effects(&mut self, messages: &mut Messages) {
+    let effects = match self.fighter {
+      Some(fighter) => fighter.effects,
+      None => return,
+    };
+
+    self.heal(effects.potency(EffectKind::Regeneration));
+    if effects.has(EffectKind::Poison) {
+      self.take_damage(effects.potency(EffectKind::Poison), messages);
+    }
+
+    // it's gone if it died
+    if let Some(fighter) = self.fighter.as_mut() {
+      for kind in fighter.effects.tick() {
+        messages.add(
+          format!("{} is no longer {}.", self.name, kind.name()),
+          colors::LIGHT_GREY,
+        );
+      }
+      self.speed_modifier = fighter.effects.speed_modifier();
+    }
+  }
+
   pub fn take_damage(&mut self, damage: i32, messages: &mut Messages) {
     // apply damage if possible
     if let Some(fighter) = self.fighter.as_mut() {
@@ -139,28 +241,78 @@ impl Object {
     }
   }
 
+  /// is there a weapon ready to swing?
+  pub fn can_attack(&self) -> bool {
+    self.weapon.is_some() && self.attacking.is_none() && self.cooldown <= 0
+  }
+
   pub fn start_attacking(&mut self, dx: i32, dy: i32) {
-    if self.attacking.is_none() {
-      self.attacking = Some((dx, dy));
+    if self.can_attack() {
+      self.attacking = Some(Swing {
+        direction: (dx, dy),
+        ticks: 0,
+        hit: vec![],
+      });
+    }
+  }
+
+  /// the positions the weapon is swinging through right now, up to the walls
+  pub fn swing_tiles(&self, map: &Map) -> Vec<(i32, i32)> {
+    match (self.weapon, self.attacking.as_ref()) {
+      (Some(weapon), Some(swing)) => weapon.reach_on(map, self.pos(), swing.direction),
+      _ => vec![],
     }
   }
 
-  pub fn stop_attacking(&mut self) {
-    self.attacking = None;
+  /// let a tick of the swing (or of the cooldown after it) go by
+  pub fn update_swing(&mut self) {
+    match (self.weapon, self.attacking.as_mut()) {
+      (Some(weapon), Some(swing)) => {
+        swing.ticks += 1;
+        if swing.ticks >= weapon.duration {
+          self.attacking = None;
+          self.cooldown = weapon.cooldown;
+        }
+      }
+      _ => self.cooldown = (self.cooldown - 1).max(0),
+    }
   }
 
-  pub fn attack(&mut self, target: &mut Object, messages: &mut Messages) {
-    // a simple formula for attack damage
-    let damage = self.fighter.map_or(0, |f| f.power) - target.fighter.map_or(0, |f| f.defense);
+  pub fn attack<R: Rng>(&mut self, target: &mut Object, rng: &mut R, messages: &mut Messages) {
+    let (attacker, defender) = match (self.fighter, target.fighter) {
+      (Some(attacker), Some(defender)) => (attacker, defender),
+      _ => return,
+    };
+
+    // roll to hit, against the target's evasion
+    let roll = rng.gen_range(1, HIT_DIE + 1);
+    let critical = roll == HIT_DIE;
+    if !critical && (roll == 1 || roll + attacker.accuracy < HIT_THRESHOLD + defender.evasion) {
+      messages.add(
+        format!("{} attacks {} and misses.", self.name, target.name),
+        colors::LIGHT_GREY,
+      );
+      return;
+    }
+
+    // critical hits roll the damage twice
+    let mut damage = attacker.damage.roll(rng);
+    if critical {
+      damage += attacker.damage.roll(rng);
+    }
+    let damage = damage - defender.defense;
+
     if damage > 0 {
       // make the target take some damage
-      messages.add(
-        format!(
-          "{} attacks {} for {} hit points.",
-          self.name, target.name, damage
-        ),
-        colors::WHITE,
+      let message = format!(
+        "{} attacks {} for {} hit points.",
+        self.name, target.name, damage
       );
+      if critical {
+        messages.add(format!("Critical hit! {}", message), colors::YELLOW);
+      } else {
+        messages.add(message, colors::WHITE);
+      }
       target.take_damage(damage, messages);
     } else {
       messages.add(
@@ -171,5 +323,11 @@ impl Object {
         colors::WHITE,
       );
     }
+
+    if critical && target.alive {
+      if let Some(effect) = attacker.on_critical {
+        target.add_effect(effect, messages);
+      }
+    }
   }
 }
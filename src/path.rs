@@ -0,0 +1,271 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use crate::map::Map;
+use crate::terrain::Terrain;
+
+/*
+ * A* path-finding over the map. Every step costs the `move_cost` of the
+ * tile it goes onto, plus whatever the caller adds for it (e.g. for tiles
+ * taken by other monsters). The search gives up after expanding
+ * `max_nodes` tiles, and then heads for the tile that got closest.
+ */
+
+const ORTHOGONAL: [(i32, i32); 4] = [(0, -1), (1, 0), (0, 1), (-1, 0)];
+const DIAGONAL: [(i32, i32); 4] = [(1, -1), (1, 1), (-1, 1), (-1, -1)];
+
+/// How hard to look for a path
+#[derive(Clone, Copy, Debug)]
+pub struct PathOptions {
+  pub diagonals: bool,
+  /// the most tiles the search expands before giving up
+  pub max_nodes: usize,
+}
+
+/// the cost of stepping onto a terrain, `None` if it can't be done.
+/// Closed doors can be walked through, after taking a turn to open them
+pub fn step_cost(terrain: Terrain) -> Option<i32> {
+  match terrain {
+    Terrain::ClosedDoor => Some(2),
+    _ => terrain.move_cost(),
+  }
+}
+
+/// the tiles next to (`x`, `y`)
+pub fn neighbours(x: i32, y: i32, diagonals: bool) -> impl Iterator<Item = (i32, i32)> {
+  let diagonal: &[(i32, i32)] = if diagonals { &DIAGONAL } else { &[] };
+  ORTHOGONAL
+    .iter()
+    .chain(diagonal.iter())
+    .map(move |&(dx, dy)| (x + dx, y + dy))
+}
+
+/// a lower bound of the cost from one tile to another
+fn estimate((x1, y1): (i32, i32), (x2, y2): (i32, i32), diagonals: bool) -> i32 {
+  let (dx, dy) = ((x2 - x1).abs(), (y2 - y1).abs());
+  if diagonals {
+    dx.max(dy)
+  } else {
+    dx + dy
+  }
+}
+
+/// a tile waiting to be expanded: (estimated total cost, cost so far, tile)
+type OpenTile = (i32, i32, (i32, i32));
+
+/// The buffers an A* search works in, kept between searches so they don't
+/// have to be allocated for every step a monster takes
+#[derive(Debug)]
+pub struct PathFinder {
+  width: i32,
+  height: i32,
+  /// the cheapest known cost to reach each tile
+  cost: Vec<i32>,
+  came_from: Vec<Option<(i32, i32)>>,
+  /// the search that last set a tile's cost; older costs count as unknown
+  searched_by: Vec<u32>,
+  search: u32,
+  open: BinaryHeap<Reverse<OpenTile>>,
+}
+
+impl PathFinder {
+  pub fn new(width: i32, height: i32) -> Self {
+    let size = (width * height) as usize;
+    PathFinder {
+      width,
+      height,
+      cost: vec![i32::MAX; size],
+      came_from: vec![None; size],
+      searched_by: vec![0; size],
+      search: 0,
+      open: BinaryHeap::new(),
+    }
+  }
+
+  fn index(&self, (x, y): (i32, i32)) -> usize {
+    (x + y * self.width) as usize
+  }
+
+  fn inside(&self, (x, y): (i32, i32)) -> bool {
+    x >= 0 && y >= 0 && x < self.width && y < self.height
+  }
+
+  fn cost(&self, pos: (i32, i32)) -> i32 {
+    let i = self.index(pos);
+    if self.searched_by[i] == self.search {
+      self.cost[i]
+    } else {
+      i32::MAX
+    }
+  }
+
+  fn set_cost(&mut self, pos: (i32, i32), cost: i32, came_from: Option<(i32, i32)>) {
+    let i = self.index(pos);
+    self.searched_by[i] = self.search;
+    self.cost[i] = cost;
+    self.came_from[i] = came_from;
+  }
+
+  /// find the cheapest path from `from` to `to`, without `from` itself.
+  /// `extra_cost` is added to the cost of stepping onto a tile. If the
+  /// target can't be reached within `options.max_nodes`, the path leads to
+  /// the tile closest to it instead. `None` if there's no way to get closer
+  pub fn find_path<F>(
+    &mut self,
+    map: &Map,
+    from: (i32, i32),
+    to: (i32, i32),
+    options: PathOptions,
+    extra_cost: F,
+  ) -> Option<Vec<(i32, i32)>>
+  where
+    F: Fn(i32, i32) -> i32,
+  {
+    if !self.inside(from) || !self.inside(to) {
+      return None;
+    }
+
+    // a new search forgets the costs of the last one
+    self.search = self.search.wrapping_add(1);
+    if self.search == 0 {
+      self
+        .searched_by
+        .iter_mut()
+        .for_each(|searched_by| *searched_by = 0);
+      self.search = 1;
+    }
+    self.open.clear();
+
+    self.set_cost(from, 0, None);
+    self
+      .open
+      .push(Reverse((estimate(from, to, options.diagonals), 0, from)));
+
+    let mut closest = (estimate(from, to, options.diagonals), from);
+    let mut expanded = 0;
+
+    while let Some(Reverse((_, cost, current))) = self.open.pop() {
+      // a cheaper way here was found after this entry was added
+      if cost > self.cost(current) {
+        continue;
+      }
+      if current == to {
+        closest = (0, to);
+        break;
+      }
+      expanded += 1;
+      if expanded > options.max_nodes {
+        break;
+      }
+
+      for next in neighbours(current.0, current.1, options.diagonals) {
+        if !self.inside(next) {
+          continue;
+        }
+        let step = match step_cost(map.tile_at(next.0, next.1).terrain) {
+          Some(step) => step,
+          None => continue,
+        };
+
+        let new_cost = cost + step + extra_cost(next.0, next.1);
+        if new_cost < self.cost(next) {
+          self.set_cost(next, new_cost, Some(current));
+
+          let remaining = estimate(next, to, options.diagonals);
+          self
+            .open
+            .push(Reverse((new_cost + remaining, new_cost, next)));
+          if remaining < closest.0 {
+            closest = (remaining, next);
+          }
+        }
+      }
+    }
+
+    // walk back from the end of the path
+    let (_, mut current) = closest;
+    let mut path = vec![];
+    while current != from {
+      path.push(current);
+      current = self.came_from[self.index(current)]?;
+    }
+    path.reverse();
+
+    if path.is_empty() {
+      None
+    } else {
+      Some(path)
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::map::Tile;
+
+  /// a 12x7 room split by a wall with a gap at the bottom
+  fn room() -> Map {
+    let mut map = Map::solid(12, 7);
+    for x in 1..11 {
+      for y in 1..6 {
+        let terrain = if x == 6 && y < 5 {
+          Terrain::Wall
+        } else {
+          Terrain::Floor
+        };
+        map.set_tile(x, y, Tile::new(terrain));
+      }
+    }
+    map
+  }
+
+  const OPTIONS: PathOptions = PathOptions {
+    diagonals: true,
+    max_nodes: 1000,
+  };
+
+  #[test]
+  fn goes_around_walls() {
+    let map = room();
+    let path = PathFinder::new(12, 7)
+      .find_path(&map, (2, 2), (9, 2), OPTIONS, |_, _| 0)
+      .unwrap();
+    assert_eq!(path.last(), Some(&(9, 2)));
+    assert!(path.contains(&(6, 5)));
+    for step in path.windows(2) {
+      assert!(step[0] != step[1] && !map.tile_at(step[1].0, step[1].1).blocked());
+    }
+  }
+
+  #[test]
+  fn reused_finder_matches_a_fresh_one() {
+    let map = room();
+    let mut finder = PathFinder::new(12, 7);
+    let searches = [
+      ((2, 2), (9, 2)),
+      ((9, 4), (1, 1)),
+      ((2, 2), (9, 2)),
+      ((10, 5), (3, 3)),
+    ];
+    for &(from, to) in &searches {
+      let fresh = PathFinder::new(12, 7).find_path(&map, from, to, OPTIONS, |_, _| 0);
+      assert_eq!(finder.find_path(&map, from, to, OPTIONS, |_, _| 0), fresh);
+    }
+  }
+
+  #[test]
+  fn gives_up_after_max_nodes() {
+    let map = room();
+    let options = PathOptions {
+      diagonals: true,
+      max_nodes: 3,
+    };
+    let path = PathFinder::new(12, 7)
+      .find_path(&map, (2, 2), (9, 2), options, |_, _| 0)
+      .unwrap();
+    // it only got part of the way, but headed towards the target
+    assert!(path.last() != Some(&(9, 2)));
+    assert!(path.len() <= 3);
+  }
+}
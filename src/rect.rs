@@ -28,15 +28,8 @@ impl Rect {
     (self.x1 <= other.x2) && (self.x2 >= other.x1) && (self.y1 <= other.y2) && (self.y2 >= other.y1)
   }
 
-  // TODO: how to do native generator?
-  // pub fn iter_points(self) -> Generator<'static, (), (i32, i32)> {
-  //   Gn::new_scoped(move |mut s| {
-  //     for x in (self.x1 + 1)..self.x2 {
-  //       for y in (self.y1 + 1)..self.y2 {
-  //         s.yield_((x, y));
-  //       }
-  //     }
-  //     done!();
-  //   })
-  // }
+  /// iterate over the points inside the rectangle (excluding its walls)
+  pub fn iter_points(self) -> impl Iterator<Item = (i32, i32)> {
+    ((self.x1 + 1)..self.x2).flat_map(move |x| ((self.y1 + 1)..self.y2).map(move |y| (x, y)))
+  }
 }
@@ -0,0 +1,349 @@
+use crate::fov::{FovAlgorithm, FOV};
+
+/*
+ * Recursive shadowcasting, as described by Björn Bergström:
+ * http://www.roguebasin.com/index.php?title=FOV_using_recursive_shadowcasting
+ *
+ * The view is split into 8 octants. Each octant is scanned row by row going
+ * away from the viewer, keeping track of the slopes that are still lit.
+ * Whenever a wall starts, the rest of the light is scanned recursively, and
+ * the scan carries on from the far side of the wall.
+ */
+
+/// how to turn the coordinates of the first octant into those of the others
+const OCTANTS: [(i32, i32, i32, i32); 8] = [
+  (1, 0, 0, 1),
+  (0, 1, 1, 0),
+  (0, -1, 1, 0),
+  (-1, 0, 0, 1),
+  (-1, 0, 0, -1),
+  (0, -1, -1, 0),
+  (0, 1, -1, 0),
+  (1, 0, 0, -1),
+];
+
+pub struct RecursiveShadowcasting;
+
+impl FovAlgorithm for RecursiveShadowcasting {
+  fn compute(&self, fov: &mut FOV, x: i32, y: i32, max_radius: i32, light_walls: bool) {
+    if !fov.in_bounds(x, y) {
+      return;
+    }
+    fov.set_in_fov(x, y);
+
+    // a radius of 0 means there's no limit
+    let radius = if max_radius > 0 {
+      max_radius
+    } else {
+      fov.width().max(fov.height())
+    };
+
+    for &octant in OCTANTS.iter() {
+      let scan = Scan {
+        origin: (x, y),
+        radius,
+        octant,
+        light_walls,
+      };
+      scan.cast_light(fov, 1, 1.0, 0.0);
+    }
+  }
+}
+
+/// The scan of a single octant
+struct Scan {
+  origin: (i32, i32),
+  radius: i32,
+  octant: (i32, i32, i32, i32),
+  light_walls: bool,
+}
+
+impl Scan {
+  /// light up the octant, starting at `row` and between the `start` and `end` slopes
+  fn cast_light(&self, fov: &mut FOV, row: i32, mut start: f32, end: f32) {
+    if start < end {
+      return;
+    }
+
+    let (cx, cy) = self.origin;
+    let (xx, xy, yx, yy) = self.octant;
+    let r2 = self.radius * self.radius;
+    let mut new_start = 0.0;
+
+    for j in row..=self.radius {
+      let dy = -j;
+      let mut blocked = false;
+
+      for dx in -j..=0 {
+        // translate the relative coordinates into map coordinates
+        let x = cx + dx * xx + dy * xy;
+        let y = cy + dx * yx + dy * yy;
+
+        // slopes to the left and right edges of this cell
+        let l_slope = (dx as f32 - 0.5) / (dy as f32 + 0.5);
+        let r_slope = (dx as f32 + 0.5) / (dy as f32 - 0.5);
+
+        if start < r_slope {
+          continue;
+        } else if end > l_slope {
+          break;
+        }
+
+        // the edge of the map blocks the view, like a wall that can't be seen
+        let inside = fov.in_bounds(x, y);
+        let transparent = inside && fov.is_transparent(x, y);
+        if inside && dx * dx + dy * dy <= r2 && (self.light_walls || transparent) {
+          fov.set_in_fov(x, y);
+        }
+
+        if blocked {
+          if transparent {
+            // the wall has ended, carry on with what's left of the light
+            blocked = false;
+            start = new_start;
+          } else {
+            // still in the shadow of the wall
+            new_start = r_slope;
+          }
+        } else if !transparent && j < self.radius {
+          // a wall starts: scan the light before it, one row further away
+          blocked = true;
+          self.cast_light(fov, j + 1, start, l_slope);
+          new_start = r_slope;
+        }
+      }
+
+      if blocked {
+        break;
+      }
+    }
+  }
+}
+
+/*
+ * Symmetric shadowcasting, as described by Albert Ford:
+ * https://www.albertford.com/shadowcasting/
+ *
+ * The view is scanned in 4 quadrants, row by row, with exact slopes. Walls
+ * are seen as soon as light reaches them, but a floor tile is only seen when
+ * its center is inside the light. That makes vision symmetric: if one floor
+ * tile can see another, the other one can see it too.
+ */
+
+/// how to turn (row, column) of the first quadrant into map offsets
+const QUADRANTS: [(i32, i32, i32, i32); 4] = [
+  (1, 0, 0, -1), // north
+  (1, 0, 0, 1),  // south
+  (0, 1, 1, 0),  // east
+  (0, -1, 1, 0), // west
+];
+
+/// An exact slope, as a fraction with a positive denominator
+#[derive(Clone, Copy)]
+struct Slope {
+  num: i32,
+  den: i32,
+}
+
+impl Slope {
+  /// the slope to the left edge of a tile
+  fn of_tile(depth: i32, col: i32) -> Self {
+    Slope {
+      num: 2 * col - 1,
+      den: 2 * depth,
+    }
+  }
+
+  /// `depth * slope`, rounded to the closest column with ties going up
+  fn round_up(self, depth: i32) -> i32 {
+    (2 * depth * self.num + self.den).div_euclid(2 * self.den)
+  }
+
+  /// `depth * slope`, rounded to the closest column with ties going down
+  fn round_down(self, depth: i32) -> i32 {
+    -(self.den - 2 * depth * self.num).div_euclid(2 * self.den)
+  }
+}
+
+pub struct SymmetricShadowcasting;
+
+impl FovAlgorithm for SymmetricShadowcasting {
+  fn compute(&self, fov: &mut FOV, x: i32, y: i32, max_radius: i32, light_walls: bool) {
+    if !fov.in_bounds(x, y) {
+      return;
+    }
+    fov.set_in_fov(x, y);
+
+    // a radius of 0 means there's no limit
+    let radius = if max_radius > 0 {
+      max_radius
+    } else {
+      fov.width().max(fov.height())
+    };
+
+    for &quadrant in QUADRANTS.iter() {
+      let scan = QuadrantScan {
+        origin: (x, y),
+        radius,
+        quadrant,
+        light_walls,
+      };
+      let start = Slope { num: -1, den: 1 };
+      let end = Slope { num: 1, den: 1 };
+      scan.scan(fov, 1, start, end);
+    }
+  }
+}
+
+/// The scan of a single quadrant
+struct QuadrantScan {
+  origin: (i32, i32),
+  radius: i32,
+  quadrant: (i32, i32, i32, i32),
+  light_walls: bool,
+}
+
+impl QuadrantScan {
+  /// map coordinates of a tile, if it is on the map
+  fn tile(&self, fov: &FOV, depth: i32, col: i32) -> Option<(i32, i32)> {
+    let (cx, cy) = self.origin;
+    let (col_x, depth_x, col_y, depth_y) = self.quadrant;
+    let (x, y) = (
+      cx + col * col_x + depth * depth_x,
+      cy + col * col_y + depth * depth_y,
+    );
+    if fov.in_bounds(x, y) {
+      Some((x, y))
+    } else {
+      None
+    }
+  }
+
+  /// tiles off the map count as walls
+  fn is_wall(&self, fov: &FOV, depth: i32, col: i32) -> bool {
+    match self.tile(fov, depth, col) {
+      Some((x, y)) => !fov.is_transparent(x, y),
+      None => true,
+    }
+  }
+
+  /// scan the row at `depth` between the `start` and `end` slopes, and the
+  /// rows behind it that the light reaches
+  fn scan(&self, fov: &mut FOV, depth: i32, mut start: Slope, end: Slope) {
+    if depth > self.radius {
+      return;
+    }
+
+    let r2 = self.radius * self.radius;
+    let mut prev_wall = None;
+
+    for col in start.round_up(depth)..=end.round_down(depth) {
+      let wall = self.is_wall(fov, depth, col);
+
+      // a floor tile is only seen if its center is lit
+      let symmetric = col * start.den >= depth * start.num && col * end.den <= depth * end.num;
+      let visible = if wall { self.light_walls } else { symmetric };
+      if visible && col * col + depth * depth <= r2 {
+        if let Some((x, y)) = self.tile(fov, depth, col) {
+          fov.set_in_fov(x, y);
+        }
+      }
+
+      match prev_wall {
+        // the light comes back in after a wall
+        Some(true) if !wall => start = Slope::of_tile(depth, col),
+        // a wall starts: the next row is lit up to its edge
+        Some(false) if wall => self.scan(fov, depth + 1, start, Slope::of_tile(depth, col)),
+        _ => {}
+      }
+      prev_wall = Some(wall);
+    }
+
+    if prev_wall == Some(false) {
+      self.scan(fov, depth + 1, start, end);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use pcg_rand::{seeds::PcgSeeder, Pcg32Basic};
+  use rand::SeedableRng;
+
+  use crate::config::GameConfig;
+  use crate::fov::{FovKind, FOV};
+  use crate::map::{Map, MapGenerator};
+
+  /// for every pair of see-through cells, check that seeing one from the
+  /// other works both ways
+  fn assert_symmetric(generator: MapGenerator, seed: u64, radius: i32) {
+    let mut rng = Pcg32Basic::from_seed(PcgSeeder::seed(seed));
+    let map = Map::new(&mut rng, generator, &GameConfig::default());
+    let mut fov = FOV::from_map(&map, FovKind::SymmetricShadowcasting);
+
+    let (w, h) = (map.width, map.height);
+    let cells: Vec<_> = (0..w)
+      .flat_map(|x| (0..h).map(move |y| (x, y)))
+      .filter(|&(x, y)| fov.is_transparent(x, y))
+      .collect();
+
+    // what every cell can see, indexed by `x + y * w`
+    let mut sees = vec![vec![]; (w * h) as usize];
+    for &(x, y) in &cells {
+      fov.compute_fov(x, y, radius, true);
+      sees[(x + y * w) as usize] = (0..w * h)
+        .map(|i| fov.is_in_fov(i % w, i / w))
+        .collect::<Vec<_>>();
+    }
+
+    for &(ax, ay) in &cells {
+      for &(bx, by) in &cells {
+        let a_sees_b = sees[(ax + ay * w) as usize][(bx + by * w) as usize];
+        let b_sees_a = sees[(bx + by * w) as usize][(ax + ay * w) as usize];
+        assert_eq!(
+          a_sees_b, b_sees_a,
+          "{:?} seed {}: ({}, {}) and ({}, {}) don't see each other the same way",
+          generator, seed, ax, ay, bx, by
+        );
+      }
+    }
+  }
+
+  #[test]
+  fn symmetric_in_rooms() {
+    for seed in 0..3 {
+      assert_symmetric(MapGenerator::Rooms, seed, 10);
+      assert_symmetric(MapGenerator::Bsp, seed, 10);
+    }
+  }
+
+  #[test]
+  fn symmetric_in_caves() {
+    for seed in 0..3 {
+      assert_symmetric(MapGenerator::Cave, seed, 10);
+    }
+  }
+
+  #[test]
+  fn symmetric_without_radius() {
+    assert_symmetric(MapGenerator::Rooms, 7, 0);
+    assert_symmetric(MapGenerator::Cave, 7, 0);
+  }
+
+  #[test]
+  fn sees_the_whole_room() {
+    let mut rng = Pcg32Basic::from_seed(PcgSeeder::seed(1));
+    let map = Map::new(&mut rng, MapGenerator::Rooms, &GameConfig::default());
+    let mut fov = FOV::from_map(&map, FovKind::SymmetricShadowcasting);
+
+    // from the middle of a room, the whole room and its walls are in view
+    let room = map.rooms[0];
+    let (x, y) = room.center();
+    fov.compute_fov(x, y, 0, true);
+    for (rx, ry) in room.iter_points() {
+      assert!(fov.is_in_fov(rx, ry), "({}, {}) is not in view", rx, ry);
+    }
+    assert!(fov.is_in_fov(room.x1, room.y1 + 1));
+    assert!(fov.is_in_fov(room.x2, room.y2 - 1));
+  }
+}
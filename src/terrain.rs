@@ -0,0 +1,104 @@
+use crate::colors::{self, Color};
+use crate::config::Palette;
+use crate::light::{Falloff, Light};
+
+/// The kind of ground a map tile is made of
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Terrain {
+  Floor,
+  Wall,
+  ShallowWater,
+  DeepWater,
+  Lava,
+  Grass,
+  Rubble,
+  Chasm,
+  ClosedDoor,
+  OpenDoor,
+  LockedDoor,
+}
+
+impl Terrain {
+  /// character used to draw the terrain by the text renderer
+  pub fn glyph(self) -> char {
+    use Terrain::*;
+    match self {
+      Floor => '.',
+      Wall => '#',
+      ShallowWater => '~',
+      DeepWater => '≈',
+      Lava => '^',
+      Grass => '"',
+      Rubble => ':',
+      Chasm => ' ',
+      ClosedDoor | LockedDoor => '+',
+      OpenDoor => '\'',
+    }
+  }
+
+  /// background color, inside (`lit`) or outside of the field of view.
+  /// Plain floors and walls take their colors from the `palette`
+  pub fn color(self, lit: bool, palette: &Palette) -> Color {
+    use Terrain::*;
+    match (self, lit) {
+      (Floor, true) => palette.light_ground,
+      (Floor, false) => palette.dark_ground,
+      (Wall, true) => palette.light_wall,
+      (Wall, false) => palette.dark_wall,
+      (ShallowWater, true) => colors::LIGHT_SKY,
+      (ShallowWater, false) => colors::DARKER_SKY,
+      (DeepWater, true) => colors::DARK_BLUE,
+      (DeepWater, false) => colors::DARKEST_BLUE,
+      (Lava, true) => colors::FLAME,
+      (Lava, false) => colors::DARKEST_FLAME,
+      (Grass, true) => colors::DARK_CHARTREUSE,
+      (Grass, false) => colors::DARKEST_CHARTREUSE,
+      (Rubble, true) => colors::DARK_SEPIA,
+      (Rubble, false) => colors::DARKEST_SEPIA,
+      (Chasm, true) => colors::DARKEST_GREY,
+      (Chasm, false) => Color::new(10, 10, 40),
+      (ClosedDoor, true) | (OpenDoor, true) => colors::DARK_AMBER,
+      (ClosedDoor, false) | (OpenDoor, false) => colors::DARKEST_AMBER,
+      (LockedDoor, true) => colors::DARK_RED,
+      (LockedDoor, false) => colors::DARKEST_RED,
+    }
+  }
+
+  /// the light given off by the terrain itself
+  pub fn light(self) -> Option<Light> {
+    match self {
+      Terrain::Lava => Some(Light::new(3, colors::FLAME, Falloff::Quadratic)),
+      _ => None,
+    }
+  }
+
+  /// how many turns it takes to walk onto this terrain, `None` if it can't be walked on
+  pub fn move_cost(self) -> Option<i32> {
+    use Terrain::*;
+    match self {
+      Floor | Grass | OpenDoor => Some(1),
+      ShallowWater | Rubble => Some(2),
+      Wall | DeepWater | Lava | Chasm | ClosedDoor | LockedDoor => None,
+    }
+  }
+
+  pub fn is_walkable(self) -> bool {
+    self.move_cost().is_some()
+  }
+
+  /// can light (and sight) pass through it?
+  pub fn is_transparent(self) -> bool {
+    use Terrain::*;
+    !matches!(self, Wall | ClosedDoor | LockedDoor)
+  }
+
+  pub fn is_door(self) -> bool {
+    use Terrain::*;
+    matches!(self, ClosedDoor | OpenDoor | LockedDoor)
+  }
+
+  /// can it be walked on, possibly after opening it?
+  pub fn is_passable(self) -> bool {
+    self.is_walkable() || self.is_door()
+  }
+}
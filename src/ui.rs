@@ -1,6 +1,7 @@
 use crate::colors::{self, Color};
-use crate::config::*;
+use crate::config::GameConfig;
 use crate::draw::{Tcod, TextAlignment};
+use crate::effects::Effects;
 
 pub struct Messages {
   messages: Vec<(String, Color)>,
@@ -21,17 +22,23 @@ impl Messages {
   //   self.messages.iter()
   // }
 
-  pub fn draw(&self, tcod: &mut Tcod) {
+  pub fn draw(&self, tcod: &mut Tcod, config: &GameConfig) {
     // print the game messages, one line at a time
-    let mut y = MSG_HEIGHT as i32;
+    let mut y = config.msg_height() as i32;
     for &(ref msg, color) in self.messages.iter().rev() {
-      let msg_height = tcod.get_height_rect(msg, MSG_X, y, MSG_WIDTH, 0);
+      let msg_height = tcod.get_height_rect(msg, config.msg_x(), y, config.msg_width(), 0);
       y -= msg_height;
       if y < 0 {
         break;
       }
       tcod.stroke(color);
-      tcod.print_rect(msg, MSG_X, PANEL_Y + y, MSG_WIDTH, 0);
+      tcod.print_rect(
+        msg,
+        config.msg_x(),
+        config.panel_y() + y,
+        config.msg_width(),
+        0,
+      );
     }
   }
 }
@@ -47,8 +54,6 @@ pub fn render_bar(
   bar_color: Color,
   back_color: Color,
 ) {
-  let y = PANEL_Y + y;
-
   // render a bar (HP, experience, etc). First calculate the width of the bar
   let bar_width = (value as f32 / maximum as f32 * total_width as f32) as i32;
 
@@ -71,3 +76,15 @@ pub fn render_bar(
     TextAlignment::Center,
   );
 }
+
+/// show the active status effects in a row, as their icon followed by the
+/// turns they have left, e.g. `!4 +7`
+pub fn render_effects(tcod: &mut Tcod, x: i32, y: i32, effects: &Effects) {
+  let mut x = x;
+  for effect in effects.iter() {
+    let text = format!("{}{}", effect.kind.icon(), effect.turns);
+    tcod.stroke(effect.kind.color());
+    tcod.print_ex(&text, x, y, TextAlignment::Left);
+    x += text.len() as i32 + 1;
+  }
+}
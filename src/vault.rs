@@ -0,0 +1,250 @@
+use rand::Rng;
+use std::collections::VecDeque;
+
+use crate::config::GameConfig;
+use crate::map::{Map, Region, Tile};
+use crate::rect::Rect;
+use crate::terrain::Terrain;
+
+/*
+ * Vaults are small hand-made rooms written as ASCII templates in `vaults.txt`.
+ * The generator stamps them into solid rock and tunnels them to the rest of the
+ * map. See `vaults.txt` for the legend.
+ */
+
+const TEMPLATES: &str = include_str!("vaults.txt");
+
+pub struct Vault {
+  pub name: String,
+  rows: Vec<Vec<char>>,
+}
+
+impl Vault {
+  pub fn width(&self) -> i32 {
+    self.rows[0].len() as i32
+  }
+
+  pub fn height(&self) -> i32 {
+    self.rows.len() as i32
+  }
+
+  /// iterate over every cell of the template, relative to its top-left corner
+  fn cells(&self) -> impl Iterator<Item = (i32, i32, char)> + '_ {
+    self.rows.iter().enumerate().flat_map(|(y, row)| {
+      row
+        .iter()
+        .enumerate()
+        .map(move |(x, &c)| (x as i32, y as i32, c))
+    })
+  }
+}
+
+/// parse all the vault templates. Panics on malformed templates, since they are
+/// embedded into the game at compile time
+pub fn templates() -> Vec<Vault> {
+  let mut vaults: Vec<Vault> = vec![];
+
+  for (i, line) in TEMPLATES.lines().enumerate() {
+    let line = line.trim_end();
+    if line.is_empty() || line.starts_with(';') {
+      continue;
+    }
+
+    if let Some(name) = line.strip_prefix("== ") {
+      vaults.push(Vault {
+        name: name.trim().into(),
+        rows: vec![],
+      });
+      continue;
+    }
+
+    let vault = vaults
+      .last_mut()
+      .unwrap_or_else(|| panic!("vaults.txt:{}: row outside of a vault", i + 1));
+    if !vault.rows.is_empty() && vault.rows[0].len() != line.chars().count() {
+      panic!(
+        "vaults.txt:{}: rows of `{}` differ in width",
+        i + 1,
+        vault.name
+      );
+    }
+    vault.rows.push(line.chars().collect());
+  }
+
+  vaults.retain(|vault| !vault.rows.is_empty());
+  vaults
+}
+
+/// try to stamp a few random vaults into the map
+pub fn place_vaults<R: Rng>(map: &mut Map, rng: &mut R, config: &GameConfig) {
+  let vaults = templates();
+  if vaults.is_empty() {
+    return;
+  }
+
+  // keys for locked vaults are hidden in the regular parts of the map
+  let key_regions = map.regions.len();
+
+  // the vaults placed so far, which tunnels have to go around
+  let mut placed: Vec<Rect> = vec![];
+  for _ in 0..config.vault_placement_tries {
+    if placed.len() >= config.max_vaults as usize {
+      break;
+    }
+
+    let vault = &vaults[rng.gen_range(0, vaults.len())];
+    let (w, h) = (vault.width(), vault.height());
+    if w + 4 >= map.width || h + 4 >= map.height {
+      continue;
+    }
+
+    // random position, keeping a margin of solid rock around it that
+    // doesn't touch the edge of the map
+    let x = rng.gen_range(2, map.width - w - 1);
+    let y = rng.gen_range(2, map.height - h - 1);
+
+    if is_solid(map, x - 1, y - 1, w + 2, h + 2) {
+      let room = stamp(map, vault, x, y, key_regions, &placed, rng);
+      placed.push(room);
+    }
+  }
+}
+
+/// is every tile in the area solid rock?
+fn is_solid(map: &Map, x: i32, y: i32, w: i32, h: i32) -> bool {
+  (x..(x + w)).all(|tx| (y..(y + h)).all(|ty| map.tile_at(tx, ty).terrain == Terrain::Wall))
+}
+
+/// carve the vault into the map, and tunnel it to the rest of the map
+/// without going through any of the `placed` vaults. Returns the vault's room
+fn stamp<R: Rng>(
+  map: &mut Map,
+  vault: &Vault,
+  x: i32,
+  y: i32,
+  key_regions: usize,
+  placed: &[Rect],
+  rng: &mut R,
+) -> Rect {
+  let mut floor = vec![];
+  let mut doors = vec![];
+
+  for (dx, dy, c) in vault.cells() {
+    let (tx, ty) = (x + dx, y + dy);
+    match c {
+      '#' => {}
+      '+' => {
+        map.set_tile(tx, ty, Tile::new(Terrain::ClosedDoor));
+        doors.push((tx, ty));
+      }
+      '=' => {
+        // a locked door needs a key somewhere outside of the vault. Without
+        // anywhere to put it, settle for a regular door
+        if key_regions > 0 {
+          let region = &map.regions[rng.gen_range(0, key_regions)];
+          let (kx, ky) = region.points[rng.gen_range(0, region.points.len())];
+          map.spawns.push((kx, ky, 'k'));
+          map.set_tile(tx, ty, Tile::new(Terrain::LockedDoor));
+        } else {
+          map.set_tile(tx, ty, Tile::new(Terrain::ClosedDoor));
+        }
+        doors.push((tx, ty));
+      }
+      _ => {
+        map.set_tile(tx, ty, Tile::empty());
+        floor.push((tx, ty));
+        if c != '.' {
+          map.spawns.push((tx, ty, c));
+        }
+      }
+    }
+  }
+
+  // the template includes its own walls, so its corners are the walls of the room
+  let room = Rect::new(x, y, vault.width() - 1, vault.height() - 1);
+
+  // tunnel from the outside of the door that is closest to the open parts of
+  // the map. A vault without doors has to be dug out of from its middle
+  let mut avoid = placed.to_vec();
+  let entrances: Vec<_> = if doors.is_empty() {
+    vec![room.center()]
+  } else {
+    avoid.push(room);
+    doors.iter().map(|&door| outside_of(&room, door)).collect()
+  };
+  let tunnel = entrances
+    .into_iter()
+    .filter_map(|entrance| find_tunnel(map, &avoid, entrance))
+    .min_by_key(Vec::len);
+  for (tx, ty) in tunnel.unwrap_or_default() {
+    map.set_tile(tx, ty, Tile::empty());
+  }
+
+  map.rooms.push(room);
+  if !floor.is_empty() {
+    let mut region = Region::from_points(floor);
+    region.vault = true;
+    map.regions.push(region);
+  }
+  room
+}
+
+/// the tile just outside a door on the edge of a room
+fn outside_of(room: &Rect, (x, y): (i32, i32)) -> (i32, i32) {
+  if x == room.x1 {
+    (x - 1, y)
+  } else if x == room.x2 {
+    (x + 1, y)
+  } else if y == room.y1 {
+    (x, y - 1)
+  } else {
+    (x, y + 1)
+  }
+}
+
+/// the shortest tunnel through solid rock from `from` to the closest open
+/// tile, staying out of the `avoid` rooms and off the edge of the map.
+/// Returns the tiles to dig out, `None` if there's no way through
+fn find_tunnel(map: &Map, avoid: &[Rect], from: (i32, i32)) -> Option<Vec<(i32, i32)>> {
+  let index = |(x, y): (i32, i32)| (x + y * map.width) as usize;
+  let allowed = |(x, y): (i32, i32)| {
+    x > 0
+      && y > 0
+      && x < map.width - 1
+      && y < map.height - 1
+      && !avoid
+        .iter()
+        .any(|room| x >= room.x1 && x <= room.x2 && y >= room.y1 && y <= room.y2)
+  };
+
+  let mut came_from = vec![None; (map.width * map.height) as usize];
+  let mut open = VecDeque::new();
+  open.push_back(from);
+  came_from[index(from)] = Some(from);
+
+  while let Some(current) = open.pop_front() {
+    if !map.tile_at(current.0, current.1).blocked() {
+      // walk back to where the tunnel started
+      let mut tunnel = vec![];
+      let mut tile = current;
+      while tile != from {
+        tile = came_from[index(tile)]?;
+        tunnel.push(tile);
+      }
+      return Some(tunnel);
+    }
+
+    let (x, y) = current;
+    for &next in &[(x + 1, y), (x - 1, y), (x, y + 1), (x, y - 1)] {
+      if !allowed(next) || came_from[index(next)].is_some() {
+        continue;
+      }
+      let terrain = map.tile_at(next.0, next.1).terrain;
+      if terrain == Terrain::Wall || !map.tile_at(next.0, next.1).blocked() {
+        came_from[index(next)] = Some(current);
+        open.push_back(next);
+      }
+    }
+  }
+  None
+}
@@ -0,0 +1,232 @@
+use crate::map::Map;
+
+/*
+ * Melee weapons. Attacking swings the weapon in a direction: for as long
+ * as the swing lasts, anything that is on (or steps into) the tiles its
+ * pattern covers gets hit, once per swing. Walls get in the way of a swing.
+ * Once the swing is over, the weapon needs a while before it can be swung
+ * again.
+ */
+
+/// the tiles around an object, going clockwise from the one above
+const AROUND: [(i32, i32); 8] = [
+  (0, -1),
+  (1, -1),
+  (1, 0),
+  (1, 1),
+  (0, 1),
+  (-1, 1),
+  (-1, 0),
+  (-1, -1),
+];
+
+/// The shape of a swing
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Pattern {
+  /// the tile in front, and the two on either side of it
+  Arc,
+  /// a straight line of tiles in front
+  Thrust(i32),
+  /// every tile around
+  Cleave,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Weapon {
+  pub name: &'static str,
+  pub pattern: Pattern,
+  /// how many ticks a swing lasts
+  pub duration: i32,
+  /// how many ticks it takes before it can be swung again
+  pub cooldown: i32,
+}
+
+pub const SWORD: Weapon = Weapon {
+  name: "sword",
+  pattern: Pattern::Arc,
+  duration: 5,
+  cooldown: 4,
+};
+
+pub const SPEAR: Weapon = Weapon {
+  name: "spear",
+  pattern: Pattern::Thrust(2),
+  duration: 6,
+  cooldown: 6,
+};
+
+pub const AXE: Weapon = Weapon {
+  name: "axe",
+  pattern: Pattern::Cleave,
+  duration: 4,
+  cooldown: 12,
+};
+
+impl Weapon {
+  /// the tiles a swing towards (`dx`, `dy`) covers, relative to whoever swings it
+  pub fn reach(&self, (dx, dy): (i32, i32)) -> Vec<(i32, i32)> {
+    match self.pattern {
+      Pattern::Arc => {
+        let front = AROUND.iter().position(|&d| d == (dx, dy)).unwrap_or(0);
+        vec![
+          AROUND[(front + 7) % 8],
+          AROUND[front],
+          AROUND[(front + 1) % 8],
+        ]
+      }
+      Pattern::Thrust(length) => (1..=length).map(|i| (dx * i, dy * i)).collect(),
+      Pattern::Cleave => AROUND.to_vec(),
+    }
+  }
+
+  /// the positions a swing towards `direction` from (`x`, `y`) covers on the
+  /// map. A thrust stops at the first wall, and a swing can't cut around the
+  /// corner between two walls
+  pub fn reach_on(&self, map: &Map, (x, y): (i32, i32), direction: (i32, i32)) -> Vec<(i32, i32)> {
+    let blocked = |(dx, dy): (i32, i32)| {
+      let (tx, ty) = (x + dx, y + dy);
+      tx < 0 || ty < 0 || tx >= map.width || ty >= map.height || map.tile_at(tx, ty).blocked()
+    };
+    let open = |&(dx, dy): &(i32, i32)| {
+      let around_corner = blocked((dx, 0)) && blocked((0, dy));
+      !(blocked((dx, dy)) || around_corner)
+    };
+
+    let offsets = self.reach(direction).into_iter();
+    let offsets: Vec<_> = match self.pattern {
+      Pattern::Thrust(_) => offsets.take_while(open).collect(),
+      _ => offsets.filter(open).collect(),
+    };
+    offsets
+      .into_iter()
+      .map(|(dx, dy)| (x + dx, y + dy))
+      .collect()
+  }
+}
+
+/// A swing in progress
+#[derive(Clone, Debug, PartialEq)]
+pub struct Swing {
+  pub direction: (i32, i32),
+  /// how many ticks it's been going on for
+  pub ticks: i32,
+  /// the objects it already hit, by their index in `Game::objects`
+  pub hit: Vec<usize>,
+}
+
+impl Swing {
+  /// the object at `id` was taken out of `Game::objects`: forget it, and
+  /// shift the ones after it down
+  pub fn forget(&mut self, id: usize) {
+    self.hit.retain(|&hit| hit != id);
+    for hit in self.hit.iter_mut().filter(|hit| **hit > id) {
+      *hit -= 1;
+    }
+  }
+}
+
+/// the character to draw a swing with, on a tile at (`dx`, `dy`) from whoever swings it
+pub fn glyph((dx, dy): (i32, i32)) -> char {
+  match (dx.signum(), dy.signum()) {
+    (0, _) => '|',
+    (_, 0) => '-',
+    (x, y) if x == y => '\\',
+    _ => '/',
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::colors;
+  use crate::config::{GameConfig, PLAYER};
+  use crate::dice::Dice;
+  use crate::effects::Effects;
+  use crate::game::Game;
+  use crate::level_file;
+  use crate::map::MapGenerator;
+  use crate::object::{Fighter, Object};
+
+  /// a room where the tile at (1, 1) can only be reached around a corner
+  const ROOM: &str = "== map\n#######\n#.#...#\n##@...#\n#.....#\n#######\n";
+
+  #[test]
+  fn walls_stop_a_thrust() {
+    let map = level_file::parse(ROOM, 80, 43).unwrap();
+    assert_eq!(SPEAR.reach_on(&map, (5, 1), (-1, 0)), vec![(4, 1), (3, 1)]);
+    assert_eq!(SPEAR.reach_on(&map, (4, 1), (-1, 0)), vec![(3, 1)]);
+    assert_eq!(SPEAR.reach_on(&map, (2, 2), (-1, 0)), vec![]);
+  }
+
+  #[test]
+  fn swings_dont_cut_corners() {
+    let map = level_file::parse(ROOM, 80, 43).unwrap();
+    assert_eq!(SWORD.reach_on(&map, (2, 2), (0, -1)), vec![(3, 1)]);
+    assert_eq!(
+      SWORD.reach_on(&map, (3, 2), (0, 1)),
+      vec![(4, 3), (3, 3), (2, 3)]
+    );
+    assert_eq!(AXE.reach_on(&map, (2, 2), (0, 1)).len(), 5);
+  }
+
+  #[test]
+  fn hits_once_per_swing() {
+    let config = GameConfig {
+      turn_based: false,
+      ..GameConfig::default()
+    };
+    let mut game = Game::new(1, MapGenerator::Rooms, config);
+    game.load_level(ROOM).unwrap();
+    game.objects.truncate(PLAYER + 1);
+    game.objects[PLAYER].weapon = Some(AXE);
+    // only a roll of 1 misses, and not with this seed
+    if let Some(fighter) = game.objects[PLAYER].fighter.as_mut() {
+      fighter.accuracy = 20;
+      fighter.damage = Dice::new(0, 1, 0);
+    }
+
+    // no damage against a defense of -1 always takes off one hit point,
+    // even when a critical hit doubles it
+    let mut dummy = Object::new(2, 3, 'o', colors::WHITE, "dummy", true);
+    dummy.alive = true;
+    dummy.fighter = Some(Fighter {
+      max_hp: 100,
+      hp: 100,
+      defense: -1,
+      accuracy: 0,
+      evasion: 0,
+      damage: Dice::new(0, 1, 0),
+      on_critical: None,
+      effects: Effects::default(),
+      mtype: 1,
+    });
+    game.objects.push(dummy);
+
+    game.objects[PLAYER].start_attacking(0, 1);
+    game.update();
+    assert_eq!(
+      game.objects[PLAYER]
+        .attacking
+        .as_ref()
+        .map(|swing| swing.hit.clone()),
+      Some(vec![1])
+    );
+    for _ in 0..AXE.duration {
+      game.update();
+    }
+    assert_eq!(game.objects[1].fighter.map(|fighter| fighter.hp), Some(99));
+  }
+
+  #[test]
+  fn forgets_removed_objects() {
+    let mut swing = Swing {
+      direction: (0, 1),
+      ticks: 0,
+      hit: vec![2, 5, 3],
+    };
+    swing.forget(3);
+    assert_eq!(swing.hit, vec![2, 4]);
+    swing.forget(1);
+    assert_eq!(swing.hit, vec![1, 3]);
+  }
+}